@@ -49,10 +49,131 @@ impl Default for Global {
     }
 }
 
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Covers {
+    pub thumbnail_format: Option<String>,
+    pub thumbnail_quality: Option<u8>,
+    pub thumbnail_sizes: Option<Vec<u32>>,
+    pub pregenerate: Option<bool>,
+}
+
+impl Default for Covers {
+    fn default() -> Self {
+        Self {
+            thumbnail_format: Some("webp".to_string()),
+            thumbnail_quality: Some(85),
+            thumbnail_sizes: Some(vec![64, 128, 256, 512]),
+            pregenerate: Some(false),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct CoverArt {
+    pub enabled: Option<bool>,
+    pub endpoint: Option<String>,
+    pub search_endpoint: Option<String>,
+    pub embed: Option<bool>,
+    pub retry_after: Option<u64>,
+}
+
+impl Default for CoverArt {
+    fn default() -> Self {
+        Self {
+            enabled: Some(false),
+            endpoint: Some("https://coverartarchive.org".to_string()),
+            search_endpoint: Some("https://musicbrainz.org/ws/2".to_string()),
+            embed: Some(false),
+            retry_after: Some(7 * 24 * 60 * 60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Library {
+    pub path_templates: Option<Vec<String>>,
+}
+
+impl Default for Library {
+    fn default() -> Self {
+        Self {
+            path_templates: Some(vec![
+                "%albumartist%/%year% - %album%/%track% - %title%".to_string(),
+                "%albumartist%/%album%/%track% - %title%".to_string(),
+                "%artist% - %title%".to_string(),
+            ]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct LyricsSource {
+    pub name: String,
+    pub endpoint: String,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Lyrics {
+    pub save_mode: Option<String>,
+    pub lrclib_endpoint: Option<String>,
+    pub retry_after: Option<u64>,
+    pub providers: Option<Vec<String>>,
+    pub local_dir: Option<String>,
+    pub fetch_interval: Option<u64>,
+    pub fetch_retries: Option<u32>,
+    pub min_confidence: Option<f32>,
+    pub sources: Option<Vec<LyricsSource>>,
+}
+
+impl Default for Lyrics {
+    fn default() -> Self {
+        Self {
+            save_mode: Some("sidecar".to_string()),
+            lrclib_endpoint: Some("https://lrclib.net/api".to_string()),
+            retry_after: Some(24 * 60 * 60),
+            providers: Some(vec![
+                "sidecar".to_string(),
+                "embedded".to_string(),
+                "local".to_string(),
+                "lrclib".to_string(),
+            ]),
+            local_dir: None,
+            fetch_interval: Some(1000),
+            fetch_retries: Some(3),
+            min_confidence: Some(0.8),
+            sources: Some(vec![]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Search {
+    pub fold_accents: Option<bool>,
+    pub stemming: Option<bool>,
+    pub cjk_ngram: Option<usize>,
+    pub normalize_kana: Option<bool>,
+}
+
+impl Default for Search {
+    fn default() -> Self {
+        Self {
+            fold_accents: Some(true),
+            stemming: Some(false),
+            cjk_ngram: Some(2),
+            normalize_kana: Some(true),
+        }
+    }
+}
+
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct Config {
     pub global: Option<Global>,
     pub network: Option<Network>,
+    pub covers: Option<Covers>,
+    pub coverart: Option<CoverArt>,
+    pub library: Option<Library>,
+    pub lyrics: Option<Lyrics>,
+    pub search: Option<Search>,
 }
 
 impl Default for Config {
@@ -60,6 +181,11 @@ impl Default for Config {
         Self {
             global: Some(Global::default()),
             network: Some(Network::default()),
+            covers: Some(Covers::default()),
+            coverart: Some(CoverArt::default()),
+            library: Some(Library::default()),
+            lyrics: Some(Lyrics::default()),
+            search: Some(Search::default()),
         }
     }
 }
@@ -83,10 +209,7 @@ impl Config {
             let mut f = std::fs::File::open(path).unwrap();
             let _ = f.read_to_string(&mut buf);
 
-            match toml::from_str::<Config>(&buf) {
-                Ok(parsed_conf) => parsed_conf,
-                Err(_) => Config::default(),
-            }
+            toml::from_str::<Config>(&buf).unwrap_or_default()
         } else {
             let conf = Config::default();
             Config::dump(path, conf.clone());
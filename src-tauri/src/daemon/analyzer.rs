@@ -0,0 +1,287 @@
+use tantivy::tokenizer::{
+    AsciiFoldingFilter, Language, LowerCaser, RemoveLongFilter, Stemmer, TextAnalyzer, Token,
+    TokenStream, Tokenizer,
+};
+
+/// Name the analyser is registered under on both indexes.
+pub const TOKENIZER: &str = "library";
+
+/// How song, album and artist text is turned into terms, from the `[search]` config.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    fold_accents: bool,
+    stemming: Option<Language>,
+    cjk_ngram: usize,
+    normalize_kana: bool,
+}
+
+impl Analysis {
+    pub fn from_config(conf: Option<lorconf::Search>, lang: Option<String>) -> Self {
+        let default = lorconf::Search::default();
+        let conf = conf.unwrap_or(default.clone());
+
+        let stemming = conf.stemming.or(default.stemming).unwrap_or(false);
+        Self {
+            fold_accents: conf.fold_accents.or(default.fold_accents).unwrap_or(true),
+            stemming: stemming
+                .then(|| lang.as_deref().and_then(language))
+                .flatten(),
+            cjk_ngram: conf.cjk_ngram.or(default.cjk_ngram).unwrap_or(2).max(1),
+            normalize_kana: conf
+                .normalize_kana
+                .or(default.normalize_kana)
+                .unwrap_or(true),
+        }
+    }
+
+    /// Stored next to the schema version, the index is built again when it changes.
+    pub fn signature(&self) -> String {
+        format!(
+            "fold={} stem={:?} ngram={} kana={}",
+            self.fold_accents, self.stemming, self.cjk_ngram, self.normalize_kana
+        )
+    }
+
+    pub fn analyzer(&self) -> TextAnalyzer {
+        self.build(self.stemming)
+    }
+
+    /// Without the stemmer, partly typed words are cut differently once stemmed.
+    pub fn prefix_analyzer(&self) -> TextAnalyzer {
+        self.build(None)
+    }
+
+    fn build(&self, stemming: Option<Language>) -> TextAnalyzer {
+        let tokenizer = LibraryTokenizer {
+            ngram: self.cjk_ngram,
+            kana: self.normalize_kana,
+        };
+        let mut builder = TextAnalyzer::builder(tokenizer)
+            .filter_dynamic(RemoveLongFilter::limit(40))
+            .filter_dynamic(LowerCaser);
+        if self.fold_accents {
+            builder = builder.filter_dynamic(AsciiFoldingFilter);
+        }
+        if let Some(language) = stemming {
+            builder = builder.filter_dynamic(Stemmer::new(language));
+        }
+
+        builder.build()
+    }
+}
+
+/// The terms of `text` joined by spaces, compared as substrings when the index
+/// can't be searched.
+pub fn normalize(analyzer: &mut TextAnalyzer, text: &str) -> String {
+    let mut terms = vec![];
+    let mut stream = analyzer.token_stream(text);
+    while stream.advance() {
+        terms.push(stream.token().text.clone());
+    }
+
+    terms.join(" ")
+}
+
+/// Stemmer language of a `Global.lang` code like `en` or `pt-BR`.
+fn language(lang: &str) -> Option<Language> {
+    let code = lang
+        .split(['-', '_'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+
+    Some(match code.as_str() {
+        "ar" => Language::Arabic,
+        "da" => Language::Danish,
+        "de" => Language::German,
+        "el" => Language::Greek,
+        "en" => Language::English,
+        "es" => Language::Spanish,
+        "fi" => Language::Finnish,
+        "fr" => Language::French,
+        "hu" => Language::Hungarian,
+        "it" => Language::Italian,
+        "nl" => Language::Dutch,
+        "no" | "nb" | "nn" => Language::Norwegian,
+        "pt" => Language::Portuguese,
+        "ro" => Language::Romanian,
+        "ru" => Language::Russian,
+        "sv" => Language::Swedish,
+        "ta" => Language::Tamil,
+        "tr" => Language::Turkish,
+        _ => return None,
+    })
+}
+
+/// Han, kana and hangul, written without spaces between words.
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{1100}'..='\u{11FF}'
+        | '\u{3005}'..='\u{3007}'
+        | '\u{3040}'..='\u{30FF}'
+        | '\u{3130}'..='\u{318F}'
+        | '\u{31F0}'..='\u{31FF}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{AC00}'..='\u{D7AF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{FF66}'..='\u{FF9F}'
+        | '\u{20000}'..='\u{2A6DF}')
+}
+
+/// Full width katakana of the half width ones, from U+FF66 to U+FF9D.
+const HALFWIDTH_KATAKANA: [u32; 56] = [
+    0x30F2, 0x30A1, 0x30A3, 0x30A5, 0x30A7, 0x30A9, 0x30E3, 0x30E5, 0x30E7, 0x30C3, 0x30FC, 0x30A2,
+    0x30A4, 0x30A6, 0x30A8, 0x30AA, 0x30AB, 0x30AD, 0x30AF, 0x30B1, 0x30B3, 0x30B5, 0x30B7, 0x30B9,
+    0x30BB, 0x30BD, 0x30BF, 0x30C1, 0x30C4, 0x30C6, 0x30C8, 0x30CA, 0x30CB, 0x30CC, 0x30CD, 0x30CE,
+    0x30CF, 0x30D2, 0x30D5, 0x30D8, 0x30DB, 0x30DE, 0x30DF, 0x30E0, 0x30E1, 0x30E2, 0x30E4, 0x30E6,
+    0x30E8, 0x30E9, 0x30EA, 0x30EB, 0x30EC, 0x30ED, 0x30EF, 0x30F3,
+];
+
+/// Katakana `c` with a following (han)dakuten, `ガ` for `カ` and `゛`.
+fn voiced(c: u32, mark: char) -> Option<u32> {
+    let dakuten = matches!(mark, '\u{FF9E}' | '\u{3099}' | '\u{309B}');
+    let handakuten = matches!(mark, '\u{FF9F}' | '\u{309A}' | '\u{309C}');
+    let row_h = matches!(c, 0x30CF | 0x30D2 | 0x30D5 | 0x30D8 | 0x30DB);
+
+    match c {
+        0x30A6 if dakuten => Some(0x30F4),
+        0x30AB..=0x30C2 if dakuten && c % 2 == 1 => Some(c + 1),
+        0x30C4 | 0x30C6 | 0x30C8 if dakuten => Some(c + 1),
+        _ if row_h && dakuten => Some(c + 1),
+        _ if row_h && handakuten => Some(c + 2),
+        _ => None,
+    }
+}
+
+/// Folds full width ASCII and half width katakana, then katakana to hiragana.
+/// Returns the character and whether `next` was merged into it.
+fn fold_char(c: char, next: Option<char>) -> (Option<char>, bool) {
+    let mut code = c as u32;
+    match code {
+        0xFF01..=0xFF5E => return (char::from_u32(code - 0xFEE0), false),
+        0x3000 => return (Some(' '), false),
+        0xFF66..=0xFF9D => code = HALFWIDTH_KATAKANA[(code - 0xFF66) as usize],
+        // Back to hiragana below, marks are merged on katakana only
+        0x3041..=0x3096 => code += 0x60,
+        // A mark that wasn't merged into the previous character
+        0xFF9E | 0xFF9F | 0x3099 | 0x309A => return (None, false),
+        _ => {}
+    }
+
+    let merged = next.and_then(|x| voiced(code, x));
+    if let Some(x) = merged {
+        code = x;
+    }
+    if (0x30A1..=0x30F6).contains(&code) {
+        code -= 0x60;
+    }
+
+    (char::from_u32(code), merged.is_some())
+}
+
+/// Splits on anything but letters and digits like tantivy's `SimpleTokenizer`,
+/// except runs of CJK characters which become overlapping n-grams.
+#[derive(Clone)]
+struct LibraryTokenizer {
+    ngram: usize,
+    kana: bool,
+}
+
+impl LibraryTokenizer {
+    fn tokens(&self, text: &str) -> Vec<Token> {
+        let chars: Vec<(usize, char)> = text.char_indices().collect();
+        let end = |i: usize| chars.get(i + 1).map_or(text.len(), |x| x.0);
+
+        // Normalised characters with the byte range they come from
+        let mut normalized = Vec::with_capacity(chars.len());
+        let mut i = 0;
+        while i < chars.len() {
+            let (start, c) = chars[i];
+            if !self.kana {
+                normalized.push((start, end(i), c));
+                i += 1;
+                continue;
+            }
+
+            let (c, merged) = fold_char(c, chars.get(i + 1).map(|x| x.1));
+            if merged {
+                i += 1;
+            }
+            if let Some(c) = c {
+                normalized.push((start, end(i), c));
+            }
+            i += 1;
+        }
+
+        let mut tokens = vec![];
+        let mut push = |run: &[(usize, usize, char)]| {
+            tokens.push(Token {
+                offset_from: run[0].0,
+                offset_to: run[run.len() - 1].1,
+                position: tokens.len(),
+                text: run.iter().map(|x| x.2).collect(),
+                position_length: 1,
+            });
+        };
+
+        let mut i = 0;
+        while i < normalized.len() {
+            let c = normalized[i].2;
+            if !c.is_alphanumeric() {
+                i += 1;
+                continue;
+            }
+
+            let cjk = is_cjk(c);
+            let mut j = i;
+            while j < normalized.len()
+                && normalized[j].2.is_alphanumeric()
+                && is_cjk(normalized[j].2) == cjk
+            {
+                j += 1;
+            }
+
+            let run = &normalized[i..j];
+            if cjk && run.len() > self.ngram {
+                run.windows(self.ngram).for_each(&mut push);
+            } else {
+                push(run);
+            }
+            i = j;
+        }
+
+        tokens
+    }
+}
+
+pub struct LibraryTokenStream {
+    tokens: Vec<Token>,
+    index: usize,
+}
+
+impl Tokenizer for LibraryTokenizer {
+    type TokenStream<'a> = LibraryTokenStream;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> Self::TokenStream<'a> {
+        LibraryTokenStream {
+            tokens: self.tokens(text),
+            index: 0,
+        }
+    }
+}
+
+impl TokenStream for LibraryTokenStream {
+    fn advance(&mut self) -> bool {
+        self.index += 1;
+        self.index <= self.tokens.len()
+    }
+
+    fn token(&self) -> &Token {
+        &self.tokens[self.index - 1]
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.tokens[self.index - 1]
+    }
+}
@@ -2,6 +2,10 @@ use std::{fs, path::PathBuf};
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 pub const _APP_ID: &str = "lorchestre";
 
+pub fn user_agent() -> String {
+    format!("L'orchestre v{VERSION} (https://github.com/luxluth/lorchestre)")
+}
+
 #[derive(Debug, Clone)]
 pub struct Dir {
     pub config: PathBuf,
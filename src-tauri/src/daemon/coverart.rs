@@ -0,0 +1,387 @@
+use lofty::picture::MimeType;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tracing::{info, warn};
+
+use super::{
+    config::user_agent,
+    global::{check_dir, Media, Track},
+    tags,
+    utils::now,
+};
+
+#[derive(serde::Deserialize)]
+struct ReleaseSearch {
+    releases: Vec<Release>,
+}
+
+#[derive(serde::Deserialize)]
+struct Release {
+    id: String,
+    score: Option<u32>,
+}
+
+// Below this MusicBrainz score the match is most likely another record
+const MIN_SCORE: u32 = 90;
+
+#[derive(Debug, Clone)]
+pub struct CoverProvider {
+    enabled: bool,
+    endpoint: String,
+    search_endpoint: String,
+    embed: bool,
+    retry_after: u64,
+    covers_dir: PathBuf,
+    misses_path: PathBuf,
+    client: reqwest::Client,
+}
+
+impl CoverProvider {
+    pub fn new(cache_dir: &Path, conf: Option<lorconf::CoverArt>) -> Self {
+        let default = lorconf::CoverArt::default();
+        let conf = conf.unwrap_or(default.clone());
+
+        Self {
+            enabled: conf.enabled.or(default.enabled).unwrap_or(false),
+            endpoint: conf
+                .endpoint
+                .or(default.endpoint)
+                .unwrap_or_default()
+                .trim_end_matches('/')
+                .to_string(),
+            search_endpoint: conf
+                .search_endpoint
+                .or(default.search_endpoint)
+                .unwrap_or_default()
+                .trim_end_matches('/')
+                .to_string(),
+            embed: conf.embed.or(default.embed).unwrap_or(false),
+            retry_after: conf.retry_after.or(default.retry_after).unwrap_or(0),
+            covers_dir: cache_dir.join("covers"),
+            misses_path: cache_dir.join(".coverart.misses"),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn misses(&self) -> HashMap<String, u64> {
+        std::fs::read(&self.misses_path)
+            .ok()
+            .and_then(|buf| serde_json::from_slice(&buf).ok())
+            .unwrap_or_default()
+    }
+
+    fn set_missed(&self, album_id: &str, missed: bool) {
+        let mut misses = self.misses();
+        if missed {
+            misses.insert(album_id.to_string(), now());
+        } else {
+            misses.remove(album_id);
+        }
+
+        if let Ok(data) = serde_json::to_vec(&misses) {
+            let _ = std::fs::write(&self.misses_path, data);
+        }
+    }
+
+    fn recently_missed(&self, album_id: &str) -> bool {
+        self.misses()
+            .get(album_id)
+            .is_some_and(|at| now().saturating_sub(*at) < self.retry_after)
+    }
+
+    /// `None` for a 404, other failures are errors so they aren't taken for a miss.
+    async fn get(&self, url: reqwest::Url) -> Result<Option<reqwest::Response>, String> {
+        let response = self
+            .client
+            .get(url)
+            .header("User-Agent", user_agent())
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await
+            .map_err(|e| format!("{e}"))?;
+
+        if response.status().is_success() {
+            Ok(Some(response))
+        } else if response.status() == reqwest::StatusCode::NOT_FOUND {
+            Ok(None)
+        } else {
+            Err(format!("{} answered {}", response.url(), response.status()))
+        }
+    }
+
+    async fn search_release(&self, album: &str, artist: &str) -> Result<Option<String>, String> {
+        let query = format!(
+            "release:\"{}\" AND artist:\"{}\"",
+            album.replace('"', ""),
+            artist.replace('"', "")
+        );
+        let url = reqwest::Url::parse_with_params(
+            &format!("{}/release/", self.search_endpoint),
+            &[("query", query.as_str()), ("fmt", "json"), ("limit", "1")],
+        )
+        .map_err(|e| format!("{e}"))?;
+
+        let Some(response) = self.get(url).await? else {
+            return Ok(None);
+        };
+        let search = response
+            .json::<ReleaseSearch>()
+            .await
+            .map_err(|e| format!("{e}"))?;
+
+        Ok(search
+            .releases
+            .into_iter()
+            .find(|x| x.score.unwrap_or(100) >= MIN_SCORE)
+            .map(|x| x.id))
+    }
+
+    async fn download(&self, mbid: &str) -> Result<Option<(Vec<u8>, MimeType)>, String> {
+        let url = reqwest::Url::parse(&format!("{}/release/{mbid}/front", self.endpoint))
+            .map_err(|e| format!("{e}"))?;
+
+        let Some(response) = self.get(url).await? else {
+            return Ok(None);
+        };
+
+        let mime = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|x| x.to_str().ok())
+            .map(MimeType::from_str)
+            .unwrap_or(MimeType::Jpeg);
+
+        let data = response.bytes().await.map_err(|e| format!("{e}"))?;
+
+        Ok(Some((data.to_vec(), mime)))
+    }
+
+    /// Looks the album up and stores its front cover in the covers cache.
+    /// Returns the cover extension, or `None` when nothing was found.
+    pub async fn fetch(
+        &self,
+        album_id: &str,
+        tracks: &[Track],
+        force: bool,
+    ) -> Result<Option<String>, String> {
+        if !force && self.recently_missed(album_id) {
+            return Ok(None);
+        }
+
+        let Some(first) = tracks.first() else {
+            return Err(format!("no tracks for the album {album_id}"));
+        };
+
+        let mbid = match tracks.iter().find_map(|x| x.musicbrainz_release_id.clone()) {
+            Some(mbid) => Some(mbid),
+            None => {
+                let artist = first
+                    .album_artist
+                    .clone()
+                    .or(first.artists.first().cloned())
+                    .unwrap_or_default();
+                self.search_release(&first.album, &artist).await?
+            }
+        };
+
+        let found = match mbid {
+            Some(mbid) => self.download(&mbid).await?,
+            None => None,
+        };
+
+        let Some((data, mime)) = found else {
+            info!("~ no cover found for {} ({album_id})", first.album);
+            self.set_missed(album_id, true);
+            return Ok(None);
+        };
+
+        let ext = match mime {
+            MimeType::Png => ".png",
+            MimeType::Gif => ".gif",
+            MimeType::Bmp => ".bmp",
+            MimeType::Tiff => ".tiff",
+            _ => ".jpeg",
+        }
+        .to_string();
+
+        check_dir(&self.covers_dir);
+        std::fs::write(self.covers_dir.join(format!("{album_id}{ext}")), &data)
+            .map_err(|e| format!("{e}"))?;
+        self.set_missed(album_id, false);
+        info!("+ cover {}{ext}", album_id);
+
+        if self.embed {
+            let paths: Vec<String> = tracks.iter().map(|x| x.file_path.clone()).collect();
+            let _ = tokio::task::spawn_blocking(move || {
+                for path in paths {
+                    if let Err(e) = tags::embed_cover(&path, data.clone(), mime.clone()) {
+                        warn!("Unable to embed the cover in {path}: {e}");
+                    }
+                }
+            })
+            .await;
+        }
+
+        Ok(Some(ext))
+    }
+
+    /// Fetches every album of `media` that has no cover yet.
+    /// Returns the album ids that got one along with the cover extension.
+    pub async fn fetch_missing(&self, media: &Media) -> Vec<(String, String)> {
+        let mut fetched = vec![];
+        for album in &media.albums {
+            if media.has_cover(&album.id, &self.covers_dir) || self.recently_missed(&album.id) {
+                continue;
+            }
+
+            let tracks = media.album_tracks(&album.id);
+            match self.fetch(&album.id, &tracks, false).await {
+                Ok(Some(ext)) => fetched.push((album.id.clone(), ext)),
+                Ok(None) => {}
+                Err(e) => warn!("Cover lookup failed for {}: {e}", album.name),
+            }
+
+            // MusicBrainz asks for at most one request per second
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+
+        fetched
+    }
+
+    pub fn covers_dir(&self) -> &Path {
+        &self.covers_dir
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::daemon::testing::{serve, temp_dir, track};
+    use axum::{
+        extract::{Query, State},
+        http::header,
+        response::IntoResponse,
+        routing::get,
+        Json, Router,
+    };
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\nstub";
+
+    #[derive(serde::Deserialize)]
+    struct Search {
+        query: String,
+    }
+
+    /// MusicBrainz and Cover Art Archive stand-in, only the "Found" album exists
+    /// and the "Down" one can't be looked up.
+    async fn stub() -> (String, Arc<AtomicUsize>) {
+        async fn search(
+            State(hits): State<Arc<AtomicUsize>>,
+            Query(search): Query<Search>,
+        ) -> axum::response::Response {
+            hits.fetch_add(1, Ordering::SeqCst);
+            if search.query.contains("release:\"Found\"") {
+                Json(serde_json::json!({ "releases": [{ "id": "found-mbid", "score": 100 }] }))
+                    .into_response()
+            } else if search.query.contains("release:\"Down\"") {
+                axum::http::StatusCode::SERVICE_UNAVAILABLE.into_response()
+            } else {
+                Json(serde_json::json!({ "releases": [] })).into_response()
+            }
+        }
+
+        async fn front(State(hits): State<Arc<AtomicUsize>>) -> impl IntoResponse {
+            hits.fetch_add(1, Ordering::SeqCst);
+            ([(header::CONTENT_TYPE, "image/png")], PNG)
+        }
+
+        let hits = Arc::new(AtomicUsize::new(0));
+        let app = Router::new()
+            .route("/ws/2/release/", get(search))
+            .route("/release/found-mbid/front", get(front))
+            .with_state(hits.clone());
+
+        (serve(app).await, hits)
+    }
+
+    fn provider(base: &str, cache_dir: &Path) -> CoverProvider {
+        CoverProvider::new(
+            cache_dir,
+            Some(lorconf::CoverArt {
+                enabled: Some(true),
+                endpoint: Some(base.to_string()),
+                search_endpoint: Some(format!("{base}/ws/2")),
+                embed: Some(false),
+                retry_after: Some(3600),
+            }),
+        )
+    }
+
+    fn album(name: &str) -> Vec<Track> {
+        vec![track("Song", "Artist", name)]
+    }
+
+    #[tokio::test]
+    async fn stores_a_found_cover() {
+        let (base, hits) = stub().await;
+        let dir = temp_dir();
+        let provider = provider(&base, dir.path());
+
+        let ext = provider.fetch("found", &album("Found"), false).await;
+        assert_eq!(ext, Ok(Some(".png".to_string())));
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+        let cover = std::fs::read(provider.covers_dir().join("found.png")).unwrap();
+        assert_eq!(cover, PNG);
+        assert!(!provider.recently_missed("found"));
+    }
+
+    #[tokio::test]
+    async fn skips_misses_until_they_expire() {
+        let (base, hits) = stub().await;
+        let dir = temp_dir();
+        let provider = provider(&base, dir.path());
+        let tracks = album("Missing");
+
+        assert_eq!(provider.fetch("missing", &tracks, false).await, Ok(None));
+        assert!(provider.recently_missed("missing"));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        // Remembered, the endpoint isn't asked again
+        assert_eq!(provider.fetch("missing", &tracks, false).await, Ok(None));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        // Unless the user asks for it
+        assert_eq!(provider.fetch("missing", &tracks, true).await, Ok(None));
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+        let expired = HashMap::from([("missing".to_string(), now() - 3601)]);
+        std::fs::write(&provider.misses_path, serde_json::to_vec(&expired).unwrap()).unwrap();
+        assert!(!provider.recently_missed("missing"));
+        assert_eq!(provider.fetch("missing", &tracks, false).await, Ok(None));
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn outages_are_not_remembered_as_misses() {
+        let (base, hits) = stub().await;
+        let dir = temp_dir();
+        let provider = provider(&base, dir.path());
+        let tracks = album("Down");
+
+        assert!(provider.fetch("down", &tracks, false).await.is_err());
+        assert!(!provider.recently_missed("down"));
+        assert!(provider.fetch("down", &tracks, false).await.is_err());
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+}
@@ -1,7 +1,23 @@
 use super::{
+    analyzer::Analysis,
     config::{self, Dir},
-    global::{self, Color, Media, SearchResults, Track},
-    list::PlaylistData,
+    coverart::CoverProvider,
+    global::{Album, Color, MapLikeAction, Media, Track},
+    index::{IndexHealth, SearchIndex},
+    infer::PathTemplate,
+    karaoke::{self, Format},
+    list::{ListFormat, PlaylistData},
+    lrc,
+    lyrics::{self, LyricsKind, SaveMode, TrackLyrics},
+    lyrics_job::{JobProgress, LyricsJob, Outcome},
+    lyrics_provider::LyricsChain,
+    mojibake::{self, Mojibake},
+    palette::Palette,
+    search::{HitKind, Page},
+    smart::{self, SmartList},
+    suggest::Suggestion,
+    tags,
+    thumbnail::{self, ThumbnailCache},
     utils,
 };
 use axum::{
@@ -10,7 +26,10 @@ use axum::{
         ws::{Message, WebSocket, WebSocketUpgrade},
         Path, Query, State,
     },
-    http::{header::CACHE_CONTROL, HeaderValue, StatusCode},
+    http::{
+        header::{CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_TYPE, ETAG, IF_NONE_MATCH, LOCATION},
+        HeaderMap, HeaderValue, StatusCode,
+    },
     response::{IntoResponse, Response},
     routing::{any, delete, get, post, put},
     Json, Router,
@@ -26,16 +45,15 @@ use base64::{
     Engine as _,
 };
 use futures::{sink::SinkExt, stream::StreamExt};
-use image::ImageReader;
 use std::{collections::HashMap, sync::Arc, time::Duration};
-use std::{
-    io::{BufWriter, Cursor, Read},
-    path::PathBuf,
-};
-use tokio::sync::RwLock;
+use std::{io::Read, path::PathBuf};
+use tokio::sync::{RwLock, RwLockWriteGuard};
 use tokio::{
     fs::File,
-    sync::mpsc::{channel, Receiver, Sender},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        watch,
+    },
 };
 use tower::ServiceBuilder;
 use tower_http::{
@@ -44,13 +62,18 @@ use tower_http::{
 };
 use tracing::{info, warn};
 
-const VERSION: &str = env!("CARGO_PKG_VERSION");
-const LRCLIB: &str = "https://lrclib.net/api";
-
 #[derive(Debug, Clone)]
 struct AppData {
     media: Arc<RwLock<Media>>,
     dirs: Dir,
+    thumbnails: Arc<ThumbnailCache>,
+    coverart: Arc<CoverProvider>,
+    lyrics: Arc<LyricsChain>,
+    lyrics_job: Arc<LyricsJob>,
+    index: Arc<SearchIndex>,
+    templates: Arc<Vec<PathTemplate>>,
+    /// Kept out of `sx`, every socket gets the latest progress on its own
+    lyrics_progress: watch::Sender<JobProgress>,
     sx: Sender<AppMessage>,
     tx: Arc<RwLock<Receiver<AppMessage>>>,
 }
@@ -59,6 +82,9 @@ enum AppMessage {
     NewMedia(Media),
     Search(String),
     LocalSearch(String),
+    LyricsSearch(String),
+    Suggest(String),
+    LyricsProgress(JobProgress),
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -73,9 +99,20 @@ async fn on_connect(ws: WebSocketUpgrade, State(state): State<AppData>) -> impl
 async fn handle_socket(socket: WebSocket, state: AppData) {
     let sx = state.sx.clone();
     let tx = state.tx.clone();
+    let mut lyrics_progress = state.lyrics_progress.subscribe();
     let (mut sender, mut receiver) = socket.split();
     tokio::spawn(async move {
-        while let Some(msg) = tx.write().await.recv().await {
+        loop {
+            let msg = tokio::select! {
+                msg = async { tx.write().await.recv().await } => match msg {
+                    Some(msg) => msg,
+                    None => break,
+                },
+                Ok(_) = lyrics_progress.changed() => {
+                    AppMessage::LyricsProgress(lyrics_progress.borrow_and_update().clone())
+                }
+            };
+
             match msg {
                 AppMessage::NewMedia(media) => {
                     let _ = sender
@@ -86,25 +123,56 @@ async fn handle_socket(socket: WebSocket, state: AppData) {
                 }
                 AppMessage::Search(query) => {
                     let media = state.media.read().await.clone();
-                    let dirs = state.dirs.clone();
-                    let res = media.search(dirs.cache, &query);
+                    check_index(&state.index, &media);
+                    let text = match media.search(&state.index, &query, Page::default()) {
+                        Ok(res) => {
+                            format!("searchresponse\n{}", serde_json::to_string(&res).unwrap())
+                        }
+                        Err(e) => format!("searcherror\n{}", serde_json::to_string(&e).unwrap()),
+                    };
+                    let _ = sender.send(Message::Text(text.into())).await;
+                }
+                AppMessage::LyricsSearch(query) => {
+                    let media = state.media.read().await.clone();
+                    check_index(&state.index, &media);
+                    let res = media.search_lyrics(&state.index, &query);
                     let _ = sender
                         .send(Message::Text(
-                            format!("searchresponse\n{}", serde_json::to_string(&res).unwrap())
-                                .into(),
+                            format!("lyricsr\n{}", serde_json::to_string(&res).unwrap()).into(),
                         ))
                         .await;
                 }
-                AppMessage::LocalSearch(query) => {
-                    let media = state.media.read().await.clone();
-                    let dirs = state.dirs.clone();
-                    let res = media.search(dirs.cache, &query);
+                AppMessage::Suggest(prefix) => {
+                    let res = SuggestResponse {
+                        suggestions: state.index.suggest(&prefix, SUGGESTIONS),
+                        prefix,
+                    };
+                    let _ = sender
+                        .send(Message::Text(
+                            format!("suggestions\n{}", serde_json::to_string(&res).unwrap()).into(),
+                        ))
+                        .await;
+                }
+                AppMessage::LyricsProgress(progress) => {
                     let _ = sender
                         .send(Message::Text(
-                            format!("localsr\n{}", serde_json::to_string(&res).unwrap()).into(),
+                            format!(
+                                "lyricsprogress\n{}",
+                                serde_json::to_string(&progress).unwrap()
+                            )
+                            .into(),
                         ))
                         .await;
                 }
+                AppMessage::LocalSearch(query) => {
+                    let media = state.media.read().await.clone();
+                    check_index(&state.index, &media);
+                    let text = match media.search(&state.index, &query, Page::default()) {
+                        Ok(res) => format!("localsr\n{}", serde_json::to_string(&res).unwrap()),
+                        Err(e) => format!("localsrerror\n{}", serde_json::to_string(&e).unwrap()),
+                    };
+                    let _ = sender.send(Message::Text(text.into())).await;
+                }
             }
         }
     });
@@ -121,6 +189,17 @@ async fn handle_socket(socket: WebSocket, state: AppData) {
                     "search" => {
                         let _ = sx.send(AppMessage::Search(body.trim().to_string())).await;
                     }
+                    "suggest" => {
+                        // Sent back as is, the client matches it with what was typed
+                        let prefix = serde_json::from_str::<String>(body)
+                            .unwrap_or_else(|_| body.trim().to_string());
+                        let _ = sx.send(AppMessage::Suggest(prefix)).await;
+                    }
+                    "lyricsearch" => {
+                        let _ = sx
+                            .send(AppMessage::LyricsSearch(body.trim().to_string()))
+                            .await;
+                    }
                     _ => {}
                 }
             }
@@ -162,9 +241,43 @@ pub async fn start(
         drop(response);
     }
 
-    let m = utils::cache_resolve(&dirs.cache, win).await;
+    let thumbnails = Arc::new(ThumbnailCache::new(&dirs.cache, config.covers));
+    let coverart = Arc::new(CoverProvider::new(&dirs.cache, config.coverart));
+    let lyrics = Arc::new(LyricsChain::from_config(&dirs.cache, config.lyrics.clone()));
+    let lyrics_job = Arc::new(LyricsJob::new(&dirs.cache, config.lyrics));
+    let templates = Arc::new(PathTemplate::from_config(config.library, &dirs.audio));
+
+    let lang = config.global.as_ref().and_then(|x| x.lang.clone());
+    let index = Arc::new(SearchIndex::open(
+        &dirs.cache,
+        Analysis::from_config(config.search, lang),
+    ));
+
+    let mut m = utils::cache_resolve(&dirs.cache, &templates, win).await;
+    sync_thumbnails(thumbnails.clone(), &m);
+    m.refresh_smart_lists(&mut index.analyzer());
+    sync_index(&index, &m, None).await;
     let media_data = Arc::new(RwLock::new(m));
     let (sx, tx) = channel(10);
+    let (lyrics_progress, _) = watch::channel(lyrics_job.progress());
+
+    let state = AppData {
+        media: media_data,
+        dirs: dirs.clone(),
+        thumbnails,
+        coverart,
+        lyrics,
+        lyrics_job,
+        index,
+        templates,
+        lyrics_progress,
+        sx,
+        tx: Arc::new(RwLock::new(tx)),
+    };
+    fetch_missing_covers(state.clone());
+    if state.lyrics_job.should_resume() {
+        run_lyrics_job(state.clone());
+    }
 
     let cors_layer = CorsLayer::new()
         .allow_origin(Any)
@@ -178,7 +291,7 @@ pub async fn start(
         .route("/media", get(media))
         .route("/localsearch", get(search))
         .route("/audio", get(audio))
-        .route("/lyrics", get(lyrics))
+        .route("/lyrics", get(lyrics).put(save_lyrics))
         .route("/album/{id}", get(album))
         // TODO: Do not cache this at all
         .route("/playlist/{path}", get(playlist))
@@ -186,17 +299,26 @@ pub async fn start(
         .route("/playlist/delete/{path}", delete(list_remove))
         .route("/playlist/create", post(list_create))
         .route("/playlist/update/{path}", put(list_update))
+        .route("/playlist/import", post(list_import))
+        .route("/playlist/export/{path}", get(list_export))
+        .route("/playlist/smart", post(smart_create))
+        .route("/playlist/smart/{path}", get(smart_list).put(smart_update))
         // ------ palylist action
         .route("/cover/{handle}", get(cover))
         .route("/updatemusic", put(updatemusic))
         .route("/search/lyrics", get(search_lyrics))
+        .route("/search/health", get(search_health))
+        .route("/suggest", get(suggest))
+        .route("/lyrics/shift", put(shift_lyrics))
+        .route("/lyrics/job", get(lyrics_job_status))
+        .route("/lyrics/job/start", post(lyrics_job_start))
+        .route("/lyrics/job/pause", post(lyrics_job_pause))
+        .route("/lyrics/job/resume", post(lyrics_job_resume))
         .route("/get_image", post(get_image))
-        .with_state(AppData {
-            media: media_data,
-            dirs: dirs.clone(),
-            sx,
-            tx: Arc::new(RwLock::new(tx)),
-        })
+        .route("/cover/fetch/{id}", post(fetch_cover))
+        .route("/tags/inferred", put(write_inferred))
+        .route("/tags/mojibake", get(list_mojibake).put(fix_mojibake))
+        .with_state(state)
         .layer(ServiceBuilder::new().layer(cors_layer))
         .layer(TraceLayer::new_for_http());
 
@@ -214,7 +336,8 @@ struct ImageGet {
 
 #[derive(serde::Serialize)]
 struct ImageGetResponse {
-    color: Color,
+    color: Option<Color>,
+    palette: Option<Palette>,
     data: String,
 }
 
@@ -224,25 +347,24 @@ async fn get_image(Json(payload): Json<ImageGet>) -> Json<ImageGetResponse> {
     let mut file_buf = vec![];
     file.read_to_end(&mut file_buf).unwrap();
 
-    let img = image::load_from_memory(&file_buf).unwrap();
-    // TODO: resize the image
-    let pixels = global::utils::get_image_buffer(img);
-    let color = color_thief::get_palette(&pixels, color_thief::ColorFormat::Rgb, 1, 2).unwrap();
-
-    let color = Color {
-        r: color[0].r,
-        g: color[0].g,
-        b: color[0].b,
-    };
+    let palette = image::load_from_memory(&file_buf)
+        .ok()
+        .and_then(Palette::from_image);
 
     let data = STANDARD.encode(&file_buf);
 
-    Json(ImageGetResponse { color, data })
+    Json(ImageGetResponse {
+        color: palette.map(|x| x.dominant),
+        palette,
+        data,
+    })
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, Debug, Default)]
 struct ImageSize {
-    size: String,
+    size: Option<String>,
+    /// The ETag of the image the url was versioned with
+    v: Option<String>,
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -251,47 +373,182 @@ struct MusicPath {
 }
 
 impl ImageSize {
-    pub fn parse(self) -> Option<(u32, u32)> {
+    pub fn parse(&self) -> Option<(u32, u32)> {
         self.size
+            .as_ref()?
             .split_once('x')
             .map(|(x, y)| (x.parse().unwrap(), y.parse().unwrap()))
     }
 }
 
-#[derive(serde::Serialize)]
-pub struct Lyrics {
-    lyrics: Vec<alrc::Line>,
+async fn lyrics(
+    State(state): State<AppData>,
+    Query(music_path): Query<MusicPath>,
+) -> Json<TrackLyrics> {
+    let path = String::from_utf8_lossy(&URL_SAFE.decode(music_path.path).unwrap()).to_string();
+
+    let track = state.media.read().await.get_song(&path);
+    let Some(track) = track else {
+        return Json(TrackLyrics::default());
+    };
+
+    match state.lyrics.best_local(&track).await {
+        Some(found) => {
+            let mut lyrics = found.lyrics;
+            // Sidecars and USLT frames are read from the disk
+            lyrics.variants =
+                tokio::task::spawn_blocking(move || lyrics::variants(&track, &found.raw))
+                    .await
+                    .unwrap_or_default();
+            Json(lyrics)
+        }
+        None => Json(TrackLyrics::default()),
+    }
 }
 
-async fn lyrics(State(state): State<AppData>, Query(music_path): Query<MusicPath>) -> Json<Lyrics> {
+#[derive(serde::Deserialize)]
+struct SaveLyrics {
+    lyrics: String,
+    mode: Option<String>,
+    format: Option<String>,
+}
+
+async fn store_lyrics(
+    state: &AppData,
+    mut track: Track,
+    input: String,
+    mode: SaveMode,
+    format: Format,
+) -> Result<(), String> {
+    let file_path = track.file_path.clone();
+    let res = {
+        let input = input.clone();
+        tokio::task::spawn_blocking(move || lyrics::save(file_path, &input, mode, format))
+            .await
+            .unwrap_or_else(|e| Err(format!("{e}")))
+    };
+
+    if res.is_ok() {
+        let path = track.file_path.clone();
+        let mut media = state.media.write().await;
+        if mode.embedded() {
+            track.embeded_lyrics = Some(lyrics::embedded_text(&input, format));
+            media.tracks.ninsert(path.clone(), track);
+            media.cache(state.dirs.cache.clone(), None);
+        }
+        reindex(&state.index, media, Some(vec![path])).await;
+    }
+
+    res
+}
+
+async fn save_lyrics(
+    State(state): State<AppData>,
+    Query(music_path): Query<MusicPath>,
+    Json(payload): Json<SaveLyrics>,
+) -> Response {
     let path = String::from_utf8_lossy(&URL_SAFE.decode(music_path.path).unwrap()).to_string();
+    let Some(track) = state.media.read().await.get_song(&path) else {
+        let mut response = format!("no song found with the id of {path}").into_response();
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return response;
+    };
 
-    if let Some(track) = state.media.read().await.get_song(&path) {
-        Json(Lyrics {
-            lyrics: track.get_lyrics(),
-        })
+    let mode = match payload.mode {
+        Some(mode) => SaveMode::from_name(&mode),
+        None => {
+            let config = lorconf::Config::get(&state.dirs.config.join("config.toml"));
+            SaveMode::from_config(config.lyrics)
+        }
+    };
+
+    let format = payload
+        .format
+        .and_then(|x| Format::from_name(&x))
+        .unwrap_or(Format::Enhanced);
+
+    let (input, lints) = if lrc::is_synced(&payload.lyrics) {
+        let input = lrc::normalize(&payload.lyrics);
+        let lints = lrc::lint(&input);
+        (input, lints)
     } else {
-        Json(Lyrics { lyrics: vec![] })
+        (payload.lyrics, vec![])
+    };
+
+    match store_lyrics(&state, track, input, mode, format).await {
+        Ok(_) => Json(lints).into_response(),
+        Err(e) => {
+            let mut response = e.into_response();
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            response
+        }
     }
 }
 
-#[allow(non_snake_case)]
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
-struct LyricsSearchResponse {
-    id: usize,
-    trackName: String,
-    artistName: String,
-    albumName: String,
-    duration: f64,
-    instrumental: bool,
-    plainLyrics: Option<String>,
-    syncedLyrics: Option<String>,
+#[derive(serde::Deserialize)]
+struct ShiftLyrics {
+    path: String,
+    ms: i64,
+}
+
+/// Moves the timestamps of the lyrics in use and saves them back where they were found,
+/// files of the lyrics directory included.
+async fn shift_lyrics(State(state): State<AppData>, Query(q): Query<ShiftLyrics>) -> Response {
+    let path = String::from_utf8_lossy(&URL_SAFE.decode(q.path).unwrap()).to_string();
+    let Some(track) = state.media.read().await.get_song(&path) else {
+        let mut response = format!("no song found with the id of {path}").into_response();
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return response;
+    };
+
+    let Some(found) = state
+        .lyrics
+        .best_local(&track)
+        .await
+        .filter(|x| x.lyrics.kind == Some(LyricsKind::Synced))
+    else {
+        let mut response = "no synced lyrics to shift".into_response();
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return response;
+    };
+
+    let mode = match found.provider.as_str() {
+        "sidecar" => SaveMode::Sidecar,
+        "embedded" => SaveMode::Embedded,
+        _ => {
+            let config = lorconf::Config::get(&state.dirs.config.join("config.toml"));
+            SaveMode::from_config(config.lyrics)
+        }
+    };
+
+    // Lyrics are shifted through Enhanced LRC and written back in their own format
+    let format = Format::detect(&found.raw).unwrap_or(Format::Lrc);
+    let input = karaoke::convert(&found.raw, Format::Enhanced);
+    let shifted = lrc::shift(&lrc::normalize(&input), q.ms);
+
+    let saved = match (found.provider.as_str(), &found.path) {
+        ("local", Some(source)) => {
+            lyrics::write_sidecar(source, &karaoke::convert(&shifted, format))
+        }
+        _ => store_lyrics(&state, track, shifted.clone(), mode, format).await,
+    };
+    match saved {
+        Ok(_) => Json(TrackLyrics::from_text(&shifted)).into_response(),
+        Err(e) => {
+            let mut response = e.into_response();
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            response
+        }
+    }
 }
 
 #[derive(serde::Serialize)]
 struct Lrc {
+    kind: Option<LyricsKind>,
     parsed: Vec<alrc::Line>,
     raw: String,
+    provider: String,
+    confidence: f32,
 }
 
 #[derive(serde::Serialize)]
@@ -302,14 +559,110 @@ struct LyricsResponse {
 #[derive(serde::Deserialize)]
 struct SearchTerm {
     term: String,
+    mode: Option<String>,
+    /// Results skipped and returned for each kind, `kind` keeps to one of them
+    offset: Option<usize>,
+    limit: Option<usize>,
+    kind: Option<HitKind>,
 }
 
-async fn search(State(state): State<AppData>, Query(q): Query<SearchTerm>) -> Json<SearchResults> {
+async fn search(State(state): State<AppData>, Query(q): Query<SearchTerm>) -> Response {
     let media = state.media.read().await;
-    let dirs = state.dirs.clone();
-    let res = media.search(dirs.cache, &q.term);
+    check_index(&state.index, &media);
+    let res = match q.mode.as_deref() {
+        Some("lyrics") => Ok(media.search_lyrics(&state.index, &q.term)),
+        _ => {
+            let page = Page {
+                offset: q.offset,
+                limit: q.limit,
+                kind: q.kind,
+            };
+            media.search(&state.index, &q.term, page)
+        }
+    };
+
+    match res {
+        Ok(res) => Json(res).into_response(),
+        Err(e) => {
+            let mut response = Json(e).into_response();
+            *response.status_mut() = StatusCode::BAD_REQUEST;
+            response
+        }
+    }
+}
+
+const SUGGESTIONS: usize = 10;
 
-    Json::from(res)
+#[derive(serde::Deserialize)]
+struct SuggestQuery {
+    prefix: String,
+    limit: Option<usize>,
+}
+
+/// The prefix is sent back so late answers to earlier keystrokes can be dropped.
+#[derive(serde::Serialize)]
+struct SuggestResponse {
+    prefix: String,
+    suggestions: Vec<Suggestion>,
+}
+
+async fn suggest(
+    State(state): State<AppData>,
+    Query(q): Query<SuggestQuery>,
+) -> Json<SuggestResponse> {
+    Json(SuggestResponse {
+        suggestions: state
+            .index
+            .suggest(&q.prefix, q.limit.unwrap_or(SUGGESTIONS)),
+        prefix: q.prefix,
+    })
+}
+
+async fn search_health(State(state): State<AppData>) -> Json<IndexHealth> {
+    Json(state.index.health())
+}
+
+/// Rebuilds the search index in the background when it is missing or broken.
+fn check_index(index: &Arc<SearchIndex>, media: &Media) {
+    if index.needs_rebuild() {
+        let index = index.clone();
+        let media = media.clone();
+        tokio::task::spawn_blocking(move || index.rebuild(&media));
+    }
+}
+
+/// Smart playlists are evaluated first so their tracks are up to date when
+/// searched or sent. The lock is released before the index is synced, the
+/// returned copy is the one to send.
+async fn reindex(
+    index: &Arc<SearchIndex>,
+    mut guard: RwLockWriteGuard<'_, Media>,
+    paths: Option<Vec<String>>,
+) -> Media {
+    guard.refresh_smart_lists(&mut index.analyzer());
+    let media = guard.clone();
+    drop(guard);
+    sync_index(index, &media, paths).await;
+    media
+}
+
+/// Syncs the songs at `paths`, or the whole library, off the runtime.
+async fn sync_index(index: &Arc<SearchIndex>, media: &Media, paths: Option<Vec<String>>) {
+    let synced = media.clone();
+    let task = index.clone();
+    let _ = tokio::task::spawn_blocking(move || match paths {
+        Some(paths) => task.sync_paths(&synced, &paths),
+        None => task.sync(&synced),
+    })
+    .await;
+    check_index(index, media);
+}
+
+/// Writes the media cache off the runtime, without holding the lock.
+async fn cache_media(state: &AppData, media: &Media) {
+    let cached = media.clone();
+    let cache_dir = state.dirs.cache.clone();
+    let _ = tokio::task::spawn_blocking(move || cached.cache(cache_dir, None)).await;
 }
 
 async fn search_lyrics(
@@ -318,64 +671,200 @@ async fn search_lyrics(
 ) -> Result<Json<LyricsResponse>, String> {
     let path = String::from_utf8_lossy(&URL_SAFE.decode(music_path.path).unwrap()).to_string();
 
-    if let Some(track) = state.media.read().await.get_song(&path) {
-        let client = reqwest::Client::new();
-        match client
-            .get(format!(
-                "{LRCLIB}/search?track_name={}&artist_name={}",
-                track.title, track.artists[0]
-            ))
-            .header(
-                "User-Agent",
-                format!("L'orchestre v{VERSION} (https://github.com/luxluth/lorchestre)"),
-            )
-            .timeout(Duration::from_secs(10))
-            .send()
-            .await
-        {
-            Ok(e) => {
-                let mut lyrics = vec![];
-                for lyric in e.json::<Vec<LyricsSearchResponse>>().await.unwrap() {
-                    if let Some(synched) = lyric.syncedLyrics {
-                        lyrics.push(Lrc {
-                            parsed: Track::parse_lyrics(&synched).unwrap().lines,
-                            raw: synched,
-                        });
-                    }
+    let Some(track) = state.media.read().await.get_song(&path) else {
+        return Err(String::from("Track not found"));
+    };
+
+    let lyrics = state
+        .lyrics
+        .search(&track)
+        .await?
+        .into_iter()
+        .map(|found| Lrc {
+            kind: found.lyrics.kind,
+            parsed: found.lyrics.lines,
+            raw: found.raw,
+            provider: found.provider,
+            confidence: found.confidence,
+        })
+        .collect();
+
+    Ok(Json(LyricsResponse { lyrics }))
+}
+
+fn send_lyrics_progress(state: &AppData) {
+    state
+        .lyrics_progress
+        .send_replace(state.lyrics_job.progress());
+}
+
+fn run_lyrics_job(state: AppData) {
+    if !state.lyrics_job.claim() {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let config = lorconf::Config::get(&state.dirs.config.join("config.toml"));
+        let mode = SaveMode::from_config(config.lyrics);
+        send_lyrics_progress(&state);
+
+        while let Some(path) = state.lyrics_job.next() {
+            let track = state.media.read().await.get_song(&path);
+            let track = match track {
+                Some(track) if !state.lyrics.local(&track).await.is_empty() => {
+                    state.lyrics_job.finish(Outcome::Skipped).await;
+                    send_lyrics_progress(&state);
+                    continue;
                 }
+                track => track,
+            };
 
-                Ok(Json(LyricsResponse { lyrics }))
-            }
-            Err(e) => Err(format!("{e:?}")),
+            let outcome = match track {
+                Some(track) => match state.lyrics_job.lookup(&state.lyrics, &track).await {
+                    Ok(Some(input)) => {
+                        match store_lyrics(&state, track, input, mode, Format::Enhanced).await {
+                            Ok(_) => Outcome::Saved,
+                            Err(e) => {
+                                warn!("Unable to save the lyrics of {path}: {e}");
+                                Outcome::Failed
+                            }
+                        }
+                    }
+                    Ok(None) => Outcome::NotFound,
+                    Err(e) => {
+                        warn!("No lyrics for {path}: {e}");
+                        Outcome::Failed
+                    }
+                },
+                // Removed from the library since the job started
+                None => Outcome::NotFound,
+            };
+
+            state.lyrics_job.finish(outcome).await;
+            send_lyrics_progress(&state);
+            state.lyrics_job.wait().await;
         }
+
+        state.lyrics_job.release().await;
+        send_lyrics_progress(&state);
+    });
+}
+
+async fn lyrics_job_status(State(state): State<AppData>) -> Json<JobProgress> {
+    Json(state.lyrics_job.progress())
+}
+
+/// Queues the whole library, tracks that already have lyrics are skipped by the job.
+async fn lyrics_job_start(State(state): State<AppData>) -> Response {
+    let pending: Vec<String> = {
+        let media = state.media.read().await;
+        media
+            .tracks
+            .iter()
+            .map(|x| x.track().file_path.clone())
+            .collect()
+    };
+
+    if !state.lyrics_job.start(pending).await {
+        let mut response = "a lyrics job is already running".into_response();
+        *response.status_mut() = StatusCode::CONFLICT;
+        return response;
+    }
+
+    run_lyrics_job(state.clone());
+    Json(state.lyrics_job.progress()).into_response()
+}
+
+async fn lyrics_job_pause(State(state): State<AppData>) -> Json<JobProgress> {
+    state.lyrics_job.pause().await;
+    send_lyrics_progress(&state);
+    Json(state.lyrics_job.progress())
+}
+
+async fn lyrics_job_resume(State(state): State<AppData>) -> Json<JobProgress> {
+    state.lyrics_job.resume().await;
+    run_lyrics_job(state.clone());
+    Json(state.lyrics_job.progress())
+}
+
+// Covers keep their url when fetched again, so unversioned urls are revalidated
+// and redirect to a url carrying the ETag, which never changes content
+const REVALIDATE: &str = "public, no-cache";
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+fn cached_image(
+    headers: &HeaderMap,
+    query: &ImageSize,
+    data: Vec<u8>,
+    mime: &str,
+    etag: String,
+) -> Response {
+    let version = etag.trim_matches('"');
+    if query.v.as_deref() != Some(version) {
+        let mut location = format!("?v={version}");
+        if let Some(size) = &query.size {
+            location.push_str(&format!("&size={size}"));
+        }
+
+        let mut resp = Response::new(Body::empty());
+        *resp.status_mut() = StatusCode::FOUND;
+        let headers = resp.headers_mut();
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static(REVALIDATE));
+        if let Ok(location) = HeaderValue::from_str(&location) {
+            headers.insert(LOCATION, location);
+        }
+        return resp;
+    }
+
+    let matches = headers
+        .get(IF_NONE_MATCH)
+        .and_then(|x| x.to_str().ok())
+        .is_some_and(|x| x.split(',').any(|tag| tag.trim() == etag));
+
+    let mut resp = if matches {
+        let mut resp = Response::new(Body::empty());
+        *resp.status_mut() = StatusCode::NOT_MODIFIED;
+        resp
     } else {
-        Err(String::from("Track not found"))
+        Response::new(Body::from(data))
+    };
+
+    let headers = resp.headers_mut();
+    headers.insert(CACHE_CONTROL, HeaderValue::from_static(IMMUTABLE));
+    if let Ok(mime) = HeaderValue::from_str(mime) {
+        headers.insert(CONTENT_TYPE, mime);
+    }
+    if let Ok(etag) = HeaderValue::from_str(&etag) {
+        headers.insert(ETAG, etag);
     }
+
+    resp
+}
+
+fn sync_thumbnails(thumbnails: Arc<ThumbnailCache>, media: &Media) {
+    let media = media.clone();
+    tokio::task::spawn_blocking(move || thumbnails.sync(&media));
 }
 
 async fn cover(
     State(state): State<AppData>,
     Path(handle): Path<String>,
-    OptionalQuery(size): OptionalQuery<ImageSize>,
+    headers: HeaderMap,
+    OptionalQuery(query): OptionalQuery<ImageSize>,
 ) -> Response {
-    let path = state.dirs.cache.join("covers").join(handle);
-
-    if let Some(image_size) = size {
-        if let Some((w, h)) = image_size.parse() {
-            if let Ok(reader) = ImageReader::open(&path) {
-                if let Ok(mut image) = reader.decode() {
-                    image = image.resize(w, h, image::imageops::FilterType::Lanczos3);
-
-                    let mut buffer = BufWriter::new(Cursor::new(Vec::new()));
-                    let _ = image.write_to(&mut buffer, image::ImageFormat::Png);
+    let path = state.dirs.cache.join("covers").join(&handle);
+    let query = query.unwrap_or_default();
 
-                    let bytes = buffer.into_inner().unwrap().into_inner();
+    if let Some((w, h)) = query.parse() {
+        let thumbnails = state.thumbnails.clone();
+        let size = thumbnails.snap(w, h);
+        let thumb = tokio::task::spawn_blocking(move || thumbnails.get(&handle, size))
+            .await
+            .ok()
+            .flatten();
 
-                    let body = Body::from(bytes);
-                    let resp = Response::new(body);
-                    return resp;
-                }
-            }
+        if let Some(thumb) = thumb {
+            return cached_image(&headers, &query, thumb.data, thumb.mime, thumb.etag);
         }
     }
 
@@ -383,33 +872,102 @@ async fn cover(
         let mut buf = vec![];
         let _ = file.read_to_end(&mut buf);
 
-        let body = Body::from(buf);
-        Response::new(body)
+        let mime = mime_guess::from_path(&path).first_or_octet_stream();
+        let etag = thumbnail::etag(&buf);
+        cached_image(&headers, &query, buf, mime.as_ref(), etag)
     } else {
         warn!("Fail to retrieve the cover file `{}`", path.display());
         let buf = include_bytes!("./assets/default-cover.png");
         let body = Body::from(buf.as_slice());
         let mut resp = Response::new(body);
 
-        resp.headers_mut().insert(
-            CACHE_CONTROL,
-            HeaderValue::from_static("public, max-age=2419200, immutable"),
-        );
+        resp.headers_mut()
+            .insert(CACHE_CONTROL, HeaderValue::from_static(REVALIDATE));
 
         resp
     }
 }
 
 async fn updatemusic(State(state): State<AppData>) {
-    let m = utils::cache_resolve(&state.dirs.cache, None).await;
+    let mut m = utils::cache_resolve(&state.dirs.cache, &state.templates, None).await;
+    sync_thumbnails(state.thumbnails.clone(), &m);
+    m.refresh_smart_lists(&mut state.index.analyzer());
+    sync_index(&state.index, &m, None).await;
     let mut binding = state.media.write().await;
     binding.swap_with(m.clone());
+    drop(binding);
     let _ = state.sx.clone().send(AppMessage::NewMedia(m)).await;
+    fetch_missing_covers(state);
+}
+
+async fn apply_covers(state: &AppData, fetched: Vec<(String, String)>) {
+    let media = {
+        let mut media = state.media.write().await;
+        for (id, ext) in fetched {
+            media.set_cover(&id, &ext, state.coverart.covers_dir());
+        }
+        media.clone()
+    };
+
+    cache_media(state, &media).await;
+    let _ = state.sx.clone().send(AppMessage::NewMedia(media)).await;
+}
+
+fn fetch_missing_covers(state: AppData) {
+    if !state.coverart.enabled() {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let media = state.media.read().await.clone();
+        let fetched = state.coverart.fetch_missing(&media).await;
+        if !fetched.is_empty() {
+            apply_covers(&state, fetched).await;
+        }
+    });
+}
+
+async fn fetch_cover(State(state): State<AppData>, Path(id): Path<String>) -> Response {
+    let tracks = state.media.read().await.album_tracks(&id);
+    if tracks.is_empty() {
+        let mut response = format!("no album found with the id of {id}").into_response();
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return response;
+    }
+
+    match state.coverart.fetch(&id, &tracks, true).await {
+        Ok(Some(ext)) => {
+            apply_covers(&state, vec![(id.clone(), ext.clone())]).await;
+            Json(ResponsePath {
+                path: format!("{id}{ext}"),
+            })
+            .into_response()
+        }
+        Ok(None) => {
+            let mut response = format!("no cover found for the album {id}").into_response();
+            *response.status_mut() = StatusCode::NOT_FOUND;
+            response
+        }
+        Err(e) => {
+            let mut response = e.into_response();
+            *response.status_mut() = StatusCode::BAD_GATEWAY;
+            response
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct AlbumResponse {
+    #[serde(flatten)]
+    album: Album,
+    palette: Option<Palette>,
 }
 
 async fn album(State(state): State<AppData>, Path(id): Path<String>) -> Response {
-    if let Some(album) = state.media.read().await.get_album(&id) {
-        Json(album).into_response()
+    let media = state.media.read().await;
+    if let Some(album) = media.get_album(&id) {
+        let palette = media.get_palette(&id);
+        Json(AlbumResponse { album, palette }).into_response()
     } else {
         let mut response = format!("no album found with the id of {id}").into_response();
         *response.status_mut() = StatusCode::NOT_FOUND;
@@ -417,8 +975,26 @@ async fn album(State(state): State<AppData>, Path(id): Path<String>) -> Response
     }
 }
 
+/// Smart playlists are evaluated again, rules such as `added:<30d` move with time.
 async fn playlist(State(state): State<AppData>, Path(path): Path<String>) -> Response {
-    if let Some(playlist) = state.media.read().await.get_playlist(path.clone()) {
+    let playlist = {
+        let media = state.media.read().await;
+        media.get_playlist(path.clone()).map(|mut playlist| {
+            if smart::is_smart(&playlist.path) {
+                let mut analyzer = state.index.analyzer();
+                match media.evaluate_smart_list(&playlist.path, &mut analyzer) {
+                    Ok((metadata, tracks)) => {
+                        playlist.metadata = metadata;
+                        playlist.tracks = tracks;
+                    }
+                    Err(e) => warn!("Unable to evaluate {}: {e}", playlist.path),
+                }
+            }
+            playlist
+        })
+    };
+
+    if let Some(playlist) = playlist {
         let mut response = Json(playlist).into_response();
         response
             .headers_mut()
@@ -441,6 +1017,7 @@ async fn list_remove(State(state): State<AppData>, Path(path): Path<String>) ->
     if let Some(playlist) = media.get_playlist(path.clone()) {
         if playlist.delete().is_ok() {
             media.remove_playlist(playlist.path);
+            reindex(&state.index, media, None).await;
             "ok".into_response()
         } else {
             let mut response =
@@ -469,15 +1046,12 @@ async fn list_update(
     let mut media = state.media.write().await;
     let dirs = state.dirs.clone();
     if let Some(mut playlist) = media.get_playlist(path) {
-        match playlist.update(metamap, payload.tracks) {
+        match playlist.update(metamap, payload.tracks, &media) {
             Ok(_) => {
                 media.substitute_playlist(playlist);
                 media.cache(dirs.cache, None);
-                let _ = state
-                    .sx
-                    .clone()
-                    .send(AppMessage::NewMedia(media.clone()))
-                    .await;
+                let media = reindex(&state.index, media, None).await;
+                let _ = state.sx.clone().send(AppMessage::NewMedia(media)).await;
                 "ok".into_response()
             }
             Err(e) => {
@@ -517,15 +1091,210 @@ async fn list_create(State(state): State<AppData>, Json(payload): Json<List>) ->
             response
         }
         Ok(path) => {
-            let m = utils::cache_resolve(&state.dirs.cache, None).await;
-            let mut binding = state.media.write().await;
-            binding.swap_with(m.clone());
+            let mut m = utils::cache_resolve(&state.dirs.cache, &state.templates, None).await;
+            m.refresh_smart_lists(&mut state.index.analyzer());
+            sync_index(&state.index, &m, None).await;
+            state.media.write().await.swap_with(m.clone());
+            let _ = state.sx.clone().send(AppMessage::NewMedia(m)).await;
+
+            Json(ResponsePath { path }).into_response()
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct ImportQuery {
+    name: Option<String>,
+    /// `m3u`, `xspf` or `pls`, guessed from the content by default
+    format: Option<String>,
+    /// Where relative entries start from, the audio directory by default
+    dir: Option<String>,
+}
+
+/// Turns an uploaded M3U, XSPF or PLS playlist into a new playlist of the library.
+async fn list_import(
+    State(state): State<AppData>,
+    Query(q): Query<ImportQuery>,
+    body: String,
+) -> Response {
+    let format = q
+        .format
+        .and_then(|x| ListFormat::from_name(&x))
+        .unwrap_or_else(|| ListFormat::detect(&body));
+    let dir = q
+        .dir
+        .map_or_else(|| state.dirs.audio.clone(), PathBuf::from);
+    let created = {
+        let media = state.media.read().await;
+        PlaylistData::import(&state.dirs.audio, &body, format, q.name, &dir, &media)
+    };
+
+    match created {
+        Err(e) => {
+            let mut response = format!("{e}").into_response();
+            *response.status_mut() = match e.kind() {
+                std::io::ErrorKind::InvalidData => StatusCode::BAD_REQUEST,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            response
+        }
+        Ok(path) => {
+            let mut m = utils::cache_resolve(&state.dirs.cache, &state.templates, None).await;
+            m.refresh_smart_lists(&mut state.index.analyzer());
+            sync_index(&state.index, &m, None).await;
+            state.media.write().await.swap_with(m.clone());
+            let _ = state.sx.clone().send(AppMessage::NewMedia(m)).await;
+
+            Json(ResponsePath { path }).into_response()
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct ExportQuery {
+    /// `m3u8` by default, `xspf`, `pls` or `playlist`
+    format: Option<String>,
+    /// `relative` or `absolute`, the default
+    paths: Option<String>,
+    /// Where relative paths start from, the audio directory by default
+    base: Option<String>,
+}
+
+/// The playlist written for another player, with titles and durations from the library.
+async fn list_export(
+    State(state): State<AppData>,
+    Path(path): Path<String>,
+    Query(q): Query<ExportQuery>,
+) -> Response {
+    let media = state.media.read().await;
+    let Some(playlist) = media.get_playlist(path.clone()) else {
+        let mut response = format!("no playlist found with the path of {path}").into_response();
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return response;
+    };
+
+    let format = match q.format {
+        Some(name) => match ListFormat::from_name(&name) {
+            Some(format) => format,
+            None => {
+                let mut response = format!("unknown playlist format {name}").into_response();
+                *response.status_mut() = StatusCode::BAD_REQUEST;
+                return response;
+            }
+        },
+        None => ListFormat::M3u,
+    };
+    let base = match q.paths.as_deref() {
+        Some("relative") => Some(
+            q.base
+                .map_or_else(|| state.dirs.audio.clone(), PathBuf::from),
+        ),
+        _ => None,
+    };
+    let output = playlist.export(format, |x| media.list_entry(x), base.as_deref());
+
+    let name: String = playlist
+        .metadata
+        .get("Name")
+        .map_or("playlist", |x| x.as_str())
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | ' ' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect();
+    let mut response = output.into_response();
+    let headers = response.headers_mut();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static(format.mime()));
+    let disposition = format!("attachment; filename=\"{name}.{}\"", format.extension());
+    if let Ok(value) = HeaderValue::from_str(&disposition) {
+        headers.insert(CONTENT_DISPOSITION, value);
+    }
+    headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+
+    response
+}
+
+/// The rules of a smart playlist, its tracks are served by `/playlist/{path}`.
+async fn smart_list(State(state): State<AppData>, Path(path): Path<String>) -> Response {
+    let playlist = state.media.read().await.get_playlist(path.clone());
+    let Some(playlist) = playlist.filter(|x| smart::is_smart(&x.path)) else {
+        let mut response =
+            format!("no smart playlist found with the path of {path}").into_response();
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return response;
+    };
+
+    match SmartList::load(&playlist.path) {
+        Ok(list) => Json(list).into_response(),
+        Err(e) => {
+            let mut response = format!("{e}").into_response();
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            response
+        }
+    }
+}
+
+async fn smart_create(State(state): State<AppData>, Json(payload): Json<SmartList>) -> Response {
+    if let Err(e) = payload.validate() {
+        let mut response = Json(e).into_response();
+        *response.status_mut() = StatusCode::BAD_REQUEST;
+        return response;
+    }
+
+    match payload.create(&state.dirs.audio) {
+        Err(e) => {
+            let mut response = format!("{e}").into_response();
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            response
+        }
+        Ok(path) => {
+            let mut m = utils::cache_resolve(&state.dirs.cache, &state.templates, None).await;
+            m.refresh_smart_lists(&mut state.index.analyzer());
+            sync_index(&state.index, &m, None).await;
+            state.media.write().await.swap_with(m.clone());
             let _ = state.sx.clone().send(AppMessage::NewMedia(m)).await;
 
             Json(ResponsePath { path }).into_response()
         }
     }
 }
+
+async fn smart_update(
+    State(state): State<AppData>,
+    Path(path): Path<String>,
+    Json(payload): Json<SmartList>,
+) -> Response {
+    if let Err(e) = payload.validate() {
+        let mut response = Json(e).into_response();
+        *response.status_mut() = StatusCode::BAD_REQUEST;
+        return response;
+    }
+
+    let mut media = state.media.write().await;
+    let playlist = media.get_playlist(path.clone());
+    let Some(playlist) = playlist.filter(|x| smart::is_smart(&x.path)) else {
+        let mut response =
+            format!("no smart playlist found with the path of {path}").into_response();
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return response;
+    };
+
+    match payload.save(&playlist.path) {
+        Ok(_) => {
+            let media = reindex(&state.index, media, None).await;
+            media.cache(state.dirs.cache.clone(), None);
+            let _ = state.sx.clone().send(AppMessage::NewMedia(media)).await;
+            "ok".into_response()
+        }
+        Err(e) => {
+            let mut response = format!("{e}").into_response();
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            response
+        }
+    }
+}
+
 async fn audio(
     range: Option<TypedHeader<Range>>,
     State(state): State<AppData>,
@@ -570,3 +1339,111 @@ async fn ping() -> String {
 async fn media(State(state): State<AppData>) -> Json<Media> {
     Json(state.media.read().await.clone())
 }
+
+async fn write_inferred(
+    State(state): State<AppData>,
+    Query(music_path): Query<MusicPath>,
+) -> Response {
+    let path = String::from_utf8_lossy(&URL_SAFE.decode(music_path.path).unwrap()).to_string();
+    let track = state.media.read().await.get_song(&path);
+
+    let Some(track) = track else {
+        let mut response = format!("no song found with the id of {path}").into_response();
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return response;
+    };
+
+    if track.inferred.is_empty() {
+        return "ok".into_response();
+    }
+
+    let written = tokio::task::spawn_blocking(move || {
+        tags::write_fields(&track.file_path, &track, &track.inferred)
+    })
+    .await
+    .unwrap_or_else(|e| Err(e.to_string()));
+
+    match written {
+        Ok(_) => {
+            let mut media = state.media.write().await;
+            if let Some(mut track) = media.get_song(&path) {
+                track.inferred.clear();
+                media.tracks.ninsert(path.clone(), track);
+            }
+            let media = reindex(&state.index, media, Some(vec![path])).await;
+            cache_media(&state, &media).await;
+            let _ = state.sx.clone().send(AppMessage::NewMedia(media)).await;
+            "ok".into_response()
+        }
+        Err(e) => {
+            let mut response = e.into_response();
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            response
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct MojibakeReport {
+    path_base64: String,
+    title: String,
+    fixes: Vec<Mojibake>,
+}
+
+async fn list_mojibake(State(state): State<AppData>) -> Json<Vec<MojibakeReport>> {
+    let media = state.media.read().await;
+    let reports = media
+        .tracks
+        .iter()
+        .map(|x| x.track())
+        .filter(|x| !x.mojibake.is_empty())
+        .map(|x| MojibakeReport {
+            path_base64: x.path_base64.clone(),
+            title: x.title.clone(),
+            fixes: x.mojibake.clone(),
+        })
+        .collect();
+
+    Json(reports)
+}
+
+async fn fix_mojibake(
+    State(state): State<AppData>,
+    Query(music_path): Query<MusicPath>,
+) -> Response {
+    let path = String::from_utf8_lossy(&URL_SAFE.decode(music_path.path).unwrap()).to_string();
+    let track = state.media.read().await.get_song(&path);
+
+    let Some(mut track) = track else {
+        let mut response = format!("no song found with the id of {path}").into_response();
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return response;
+    };
+
+    let fields = mojibake::apply(&mut track);
+    if fields.is_empty() {
+        return "ok".into_response();
+    }
+
+    let written =
+        tokio::task::spawn_blocking(move || tags::write_fields(&track.file_path, &track, &fields))
+            .await
+            .unwrap_or_else(|e| Err(e.to_string()));
+
+    match written {
+        Ok(_) => {
+            let covers_dir = state.dirs.cache.join("covers");
+            let mut media = state.media.write().await;
+            media.reload_song(&path, &covers_dir, &state.templates);
+            let media = reindex(&state.index, media, Some(vec![path])).await;
+            cache_media(&state, &media).await;
+            let _ = state.sx.clone().send(AppMessage::NewMedia(media)).await;
+            "ok".into_response()
+        }
+        Err(e) => {
+            let mut response = e.into_response();
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            response
+        }
+    }
+}
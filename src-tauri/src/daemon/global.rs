@@ -1,23 +1,32 @@
-use crate::daemon::list;
+use crate::daemon::{
+    analyzer, index, infer, list, lyrics, mojibake, palette, query, search, smart, thumbnail,
+};
 use base64::{engine::general_purpose::URL_SAFE, Engine as _};
 use bitcode::{Decode, Encode};
-use color_thief::ColorFormat;
-use list::PlaylistData;
+use index::{SearchIndex, SongFields};
+use list::{ListEntry, PlaylistData, PlaylistMetadata};
 use lofty::picture::{MimeType, PictureType};
 use lofty::prelude::*;
 use lofty::probe::Probe;
 use mime_guess::{self, mime};
-use std::collections::HashMap;
+use palette::Palette;
+use query::{Attributes, Group, ParseError, TextField};
+use search::{HitKind, Hits, Page, SearchHit};
+use smart::SmartList;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::io::{Read, Write};
+use std::io::Write;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::SystemTime;
 use tantivy::collector::{Count, TopDocs};
-use tantivy::query::FuzzyTermQuery;
+use tantivy::query::QueryParser;
 use tantivy::schema::*;
-use tantivy::{doc, Index, IndexWriter, ReloadPolicy};
+use tantivy::snippet::SnippetGenerator;
+use tantivy::tokenizer::TextAnalyzer;
+use tantivy::Searcher;
 use tauri::Emitter;
-use tracing::{error, warn};
+use tracing::warn;
 
 #[derive(serde::Serialize, Debug)]
 pub struct Cover {
@@ -25,7 +34,9 @@ pub struct Cover {
     ext: String,
 }
 
-#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, Encode, Decode)]
+#[derive(
+    serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Encode, Decode,
+)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
@@ -87,6 +98,19 @@ pub struct Track {
     pub genres: Vec<String>,
     pub tracks_count: u32,
     pub embeded_lyrics: Option<String>,
+    pub musicbrainz_release_id: Option<String>,
+    /// Fields filled from the file path instead of the tags
+    #[serde(default)]
+    pub inferred: Vec<String>,
+    /// Tags that look like they were decoded with the wrong charset
+    #[serde(default)]
+    pub mojibake: Vec<mojibake::Mojibake>,
+    /// From the `PLAYCOUNT` or `FMPS_PLAYCOUNT` tag
+    #[serde(default)]
+    pub plays: u64,
+    /// Stars from 0 to 5, from the `RATING` or `FMPS_RATING` tag
+    #[serde(default)]
+    pub rating: Option<u8>,
 
     pub created_at: u64,
 }
@@ -100,7 +124,11 @@ impl PartialEq for Track {
 impl Eq for Track {}
 
 impl Track {
-    pub fn from_file(covers_dir: &PathBuf, inode: PathBuf) -> Result<Self, ()> {
+    pub fn from_file(
+        covers_dir: &PathBuf,
+        inode: PathBuf,
+        templates: &[infer::PathTemplate],
+    ) -> Result<Self, ()> {
         if let Ok(tagged_file) = Probe::open(&inode).unwrap().read() {
             let properties = tagged_file.properties();
             let bitrate = properties.audio_bitrate().unwrap_or(0);
@@ -181,10 +209,17 @@ impl Track {
                 audio.album = album.to_string();
             }
 
-            if let Some(album_artist) = tag.get_string(&ItemKey::OriginalArtist) {
+            if let Some(album_artist) = tag
+                .get_string(&ItemKey::OriginalArtist)
+                .or(tag.get_string(&ItemKey::AlbumArtist))
+            {
                 audio.album_artist = Some(album_artist.to_string());
             }
 
+            if let Some(mbid) = tag.get_string(&ItemKey::MusicBrainzReleaseId) {
+                audio.musicbrainz_release_id = Some(mbid.to_string());
+            }
+
             if let Some(no) = tag.track() {
                 audio.track = no;
             }
@@ -193,6 +228,34 @@ impl Track {
                 audio.tracks_count = tt;
             }
 
+            // Left at 0 until the path templates had a chance to fill it
+            audio.disc = tag.disk().unwrap_or(0);
+            audio.disc_total = tag.disk_total().unwrap_or(1);
+
+            let number = |key: &str| {
+                tag.get_string(&ItemKey::Unknown(key.into()))
+                    .and_then(|x| x.trim().parse::<f64>().ok())
+                    .filter(|x| *x >= 0.0)
+            };
+            if let Some(plays) = number("PLAYCOUNT").or_else(|| number("FMPS_PLAYCOUNT")) {
+                audio.plays = plays as u64;
+            }
+            audio.rating = match (number("FMPS_RATING"), number("RATING")) {
+                (Some(x), _) => Some(x.min(1.0) * 5.0),
+                // Stars, percents or a POPM byte
+                (_, Some(x)) if x <= 5.0 => Some(x),
+                (_, Some(x)) if x <= 100.0 => Some(x / 20.0),
+                (_, Some(x)) => Some(x.min(255.0) / 51.0),
+                _ => None,
+            }
+            .map(|x| x.round() as u8);
+
+            audio.mojibake = mojibake::scan(&audio);
+            infer::infer(&mut audio, &inode, templates);
+            if audio.disc == 0 {
+                audio.disc = 1;
+            }
+
             let mut bytes = audio.album.as_bytes().to_vec();
             bytes.extend(
                 audio
@@ -206,9 +269,6 @@ impl Track {
 
             audio.album_id = format!("{digest:x}");
 
-            audio.disc = tag.disk().unwrap_or(1);
-            audio.disc_total = tag.disk_total().unwrap_or(1);
-
             let possible_covers = [
                 PictureType::CoverFront,
                 PictureType::Media,
@@ -243,19 +303,6 @@ impl Track {
                         f.write_all(&cover.data).unwrap();
                     }
 
-                    let img = image::open(cover_path).unwrap();
-                    let pixels = utils::get_image_buffer(img);
-
-                    let color = color_thief::get_palette(&pixels, ColorFormat::Rgb, 1, 2).unwrap();
-
-                    let color = Color {
-                        r: color[0].r,
-                        g: color[0].g,
-                        b: color[0].b,
-                    };
-
-                    audio.is_light = Some(color.is_light_color());
-                    audio.color = Some(color);
                     audio.cover_ext = cover.ext;
 
                     break 'cover_loop;
@@ -280,39 +327,6 @@ impl Track {
     pub fn parse_lyrics(input: &str) -> Result<alrc::AdvancedLrc, String> {
         alrc::AdvancedLrc::parse(input)
     }
-
-    pub fn get_lyrics(&self) -> Vec<alrc::Line> {
-        let lrc_path = PathBuf::from(&self.file_path).with_extension("lrc");
-        if lrc_path.exists() {
-            let mut f = fs::File::open(&lrc_path).unwrap();
-            let mut buf = Vec::new();
-            f.read_to_end(&mut buf).unwrap();
-            let buf = String::from_utf8(buf);
-            match buf {
-                Ok(buf) => match Track::parse_lyrics(&buf) {
-                    Ok(parsed_lyrics) => parsed_lyrics.lines,
-                    Err(e) => {
-                        error!("{}", e);
-                        vec![]
-                    }
-                },
-                Err(e) => {
-                    error!("{}", e);
-                    vec![]
-                }
-            }
-        } else if self.embeded_lyrics.is_some() {
-            if let Ok(parsed_lyrics) =
-                alrc::AdvancedLrc::parse(&self.embeded_lyrics.clone().unwrap())
-            {
-                return parsed_lyrics.lines;
-            } else {
-                vec![]
-            }
-        } else {
-            vec![]
-        }
-    }
 }
 
 impl Default for Track {
@@ -339,6 +353,11 @@ impl Default for Track {
             encoder: "Unknown".into(),
             genres: vec![],
             embeded_lyrics: None,
+            musicbrainz_release_id: None,
+            inferred: vec![],
+            mojibake: vec![],
+            plays: 0,
+            rating: None,
             tracks_count: 0,
         }
     }
@@ -352,105 +371,231 @@ pub struct Songs {
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Encode, Decode)]
 pub struct TrackCollection(String, Track);
 
+impl TrackCollection {
+    pub fn track(&self) -> &Track {
+        &self.1
+    }
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Default, Debug, Clone, Encode, Decode)]
 pub struct Media {
     pub tracks: Vec<TrackCollection>,
     pub albums: Vec<Album>,
     pub playlists: Vec<PlaylistData>,
+    #[serde(default)]
+    pub palettes: HashMap<String, Palette>,
 }
 
 impl Media {
-    pub fn search(&self, cache_dir: PathBuf, query: &str) -> SearchResults {
-        let songs_index_path = cache_dir.join(".search.songs");
-        let albums_index_path = cache_dir.join(".search.albums");
-        let songs_index = Index::open_in_dir(songs_index_path).unwrap();
-        let albums_index = Index::open_in_dir(albums_index_path).unwrap();
-
-        let mut schema_builder = Schema::builder();
-        let title = schema_builder.add_text_field("title", TEXT | STORED);
-        let artists = schema_builder.add_text_field("artists", TEXT | STORED);
-        let album = schema_builder.add_text_field("album", TEXT | STORED);
-        schema_builder.add_text_field("path", TEXT | STORED);
-        let songs_schema = schema_builder.build();
-
-        schema_builder = tantivy::schema::Schema::builder();
-        let album_name = schema_builder.add_text_field("name", TEXT | STORED);
-        let album_artist = schema_builder.add_text_field("artist", TEXT | STORED);
-        schema_builder.add_text_field("id", TEXT | STORED);
-        let albums_schema = schema_builder.build();
-
-        let songs_reader = songs_index
-            .reader_builder()
-            .reload_policy(ReloadPolicy::OnCommitWithDelay)
-            .try_into()
-            .unwrap();
-
-        let albums_reader = albums_index
-            .reader_builder()
-            .reload_policy(ReloadPolicy::OnCommitWithDelay)
-            .try_into()
-            .unwrap();
-
-        let mut tracks = vec![];
-        let mut albums = vec![];
+    /// Everything matching a query of [`query::parse`], best matches first.
+    pub fn search(
+        &self,
+        index: &SearchIndex,
+        query: &str,
+        page: Page,
+    ) -> Result<SearchResults, ParseError> {
+        let parsed = query::parse(query)?;
+        // Plain words keep to the best matches, filters list everything they match
+        let plain = parsed.is_plain();
+        let lookup = Lookup::new(self, index.facets());
+
+        let mut hits = Hits::default();
+        let Some(indexes) = index.ready() else {
+            // Substrings while the index is rebuilt are better than nothing
+            let mut analyzer = index.analyzer();
+            let mut normalize = |text: &str| analyzer::normalize(&mut analyzer, text);
+            for group in &parsed.groups {
+                let mut add = |kind, ids: Vec<&str>| {
+                    ids.into_iter().for_each(|id| hits.add(kind, id, 0.0));
+                };
+                if page.wants(HitKind::Track) {
+                    let tracks = lookup.tracks.iter().map(|(id, x)| (*id, *x));
+                    add(HitKind::Track, scan(group, tracks, &mut normalize));
+                }
+                if page.wants(HitKind::Album) && group.applies_to_albums() {
+                    let albums = self.albums.iter().map(|x| (x.id.as_str(), x));
+                    add(HitKind::Album, scan(group, albums, &mut normalize));
+                }
+                if !group.applies_to_collections() {
+                    continue;
+                }
+                if page.wants(HitKind::Playlist) {
+                    let playlists = self.playlists.iter().map(|x| (x.path.as_str(), x));
+                    add(HitKind::Playlist, scan(group, playlists, &mut normalize));
+                }
+                if page.wants(HitKind::Artist) {
+                    let artists = lookup.facets.artists.iter().map(|(id, x)| (id.as_str(), x));
+                    add(HitKind::Artist, scan(group, artists, &mut normalize));
+                }
+                if page.wants(HitKind::Genre) {
+                    let genres = lookup.facets.genres.iter().map(|(id, x)| (id.as_str(), x));
+                    add(HitKind::Genre, scan(group, genres, &mut normalize));
+                }
+            }
 
-        let song_searcher = songs_reader.searcher();
-        let album_searcher = albums_reader.searcher();
-
-        let terms = [
-            Term::from_field_text(title, query),
-            Term::from_field_text(artists, query),
-            Term::from_field_text(album, query),
-        ];
-
-        for term in terms {
-            let q = FuzzyTermQuery::new(term, 2, true);
-            let (top_docs, _) = song_searcher
-                .search(&q, &(TopDocs::with_limit(10), Count))
-                .unwrap();
-
-            for (_, doc_address) in top_docs {
-                let doc: TantivyDocument = song_searcher.doc(doc_address).unwrap();
-                let doc = doc.to_named_doc(&songs_schema);
-
-                if let Some(paths) = doc.0.get("path") {
-                    if let OwnedValue::Str(p) = paths[0].clone() {
-                        if let Some(track) = self.get_song(&p) {
-                            tracks.push(track);
-                        };
+            return Ok(results(hits, HashMap::new(), &lookup, page, plain));
+        };
+
+        let SongFields {
+            title,
+            artists,
+            album,
+            path,
+            lyrics: lyrics_text,
+            ..
+        } = index.song;
+        let (album_name, album_artist, album_id) =
+            (index.album.name, index.album.artist, index.album.id);
+        let collection = index.collection;
+        let song_searcher = indexes.songs_searcher();
+        let album_searcher = indexes.albums_searcher();
+        let collection_searcher = indexes.collections_searcher();
+
+        // Totals straight from the index, only right for a single group
+        let mut counts = HashMap::new();
+        for group in &parsed.groups {
+            if page.wants(HitKind::Track) {
+                let q = group.text_query(&indexes.songs, |field| match field {
+                    None => vec![title, artists, album],
+                    Some(TextField::Title) => vec![title],
+                    Some(TextField::Artist) => vec![artists],
+                    Some(TextField::Album) => vec![album],
+                    Some(TextField::Lyrics) => vec![lyrics_text],
+                });
+                let fetch = page.fetch(HitKind::Track, plain);
+                let (found, total) = match q {
+                    Some(q) => top_keys(&song_searcher, &*q, path, fetch),
+                    // Every term was dropped by the analyser
+                    None if group.has_text() => (vec![], 0),
+                    None => (
+                        lookup.tracks.keys().map(|x| (0.0, x.to_string())).collect(),
+                        lookup.tracks.len(),
+                    ),
+                };
+                counts.insert(HitKind::Track, total);
+                for (score, p) in found {
+                    if lookup
+                        .tracks
+                        .get(p.as_str())
+                        .is_some_and(|x| group.matches(*x))
+                    {
+                        hits.add(HitKind::Track, &p, score);
                     }
                 }
             }
-        }
 
-        let terms = [
-            Term::from_field_text(album_name, query),
-            Term::from_field_text(album_artist, query),
-        ];
-
-        for term in terms {
-            let q = FuzzyTermQuery::new(term, 2, true);
-            let (top_docs, _) = album_searcher
-                .search(&q, &(TopDocs::with_limit(5), Count))
-                .unwrap();
+            if page.wants(HitKind::Album) && group.applies_to_albums() {
+                let q = group.text_query(&indexes.albums, |field| match field {
+                    None => vec![album_name, album_artist],
+                    Some(TextField::Album) => vec![album_name],
+                    Some(TextField::Artist) => vec![album_artist],
+                    _ => vec![],
+                });
+                let fetch = page.fetch(HitKind::Album, plain);
+                let (found, total) = match q {
+                    Some(q) => top_keys(&album_searcher, &*q, album_id, fetch),
+                    None if group.has_text() => (vec![], 0),
+                    None => (
+                        lookup.albums.keys().map(|x| (0.0, x.to_string())).collect(),
+                        lookup.albums.len(),
+                    ),
+                };
+                counts.insert(HitKind::Album, total);
+                for (score, id) in found {
+                    if lookup
+                        .albums
+                        .get(id.as_str())
+                        .is_some_and(|x| group.matches(*x))
+                    {
+                        hits.add(HitKind::Album, &id, score);
+                    }
+                }
+            }
 
-            for (_, doc_address) in top_docs {
-                let doc: TantivyDocument = album_searcher.doc(doc_address).unwrap();
-                let doc = doc.to_named_doc(&albums_schema);
-                if let Some(ids) = doc.0.get("id") {
-                    if let OwnedValue::Str(id) = ids[0].clone() {
-                        if let Some(album) = self.get_album(&id) {
-                            albums.push(album);
-                        };
+            if !group.applies_to_collections() {
+                continue;
+            }
+            let Some(q) = group.text_query(&indexes.collections, |field| match field {
+                None => vec![collection.name, collection.description],
+                _ => vec![],
+            }) else {
+                continue;
+            };
+            for kind in HitKind::COLLECTIONS {
+                if !page.wants(kind) {
+                    continue;
+                }
+                let q = search::of_kind(q.box_clone(), collection.kind, kind);
+                let fetch = page.fetch(kind, plain);
+                let (found, total) = top_keys(&collection_searcher, &*q, collection.key, fetch);
+                counts.insert(kind, total);
+                for (score, key) in found {
+                    match index::collection_key(&key) {
+                        Some((kind, id)) if lookup.has(kind, id) => hits.add(kind, id, score),
+                        _ => {}
                     }
                 }
             }
         }
 
-        albums.dedup();
-        tracks.dedup();
+        if !plain {
+            counts.clear();
+        }
+        Ok(results(hits, counts, &lookup, page, plain))
+    }
+
+    /// Tracks whose lyrics match `query`, with a highlighted snippet and the time of
+    /// the best matching line for synced lyrics. Nothing while the index is rebuilt.
+    pub fn search_lyrics(&self, index: &SearchIndex, query: &str) -> SearchResults {
+        let SongFields {
+            path,
+            lyrics: lyrics_text,
+            synced,
+            ..
+        } = index.song;
+        let Some(indexes) = index.ready() else {
+            return SearchResults::default();
+        };
+        let searcher = indexes.songs_searcher();
+
+        let parser = QueryParser::for_index(&indexes.songs, vec![lyrics_text]);
+        let (q, _) = parser.parse_query_lenient(query);
+        let Ok(top_docs) = searcher.search(&q, &TopDocs::with_limit(20)) else {
+            return SearchResults::default();
+        };
+        let Ok(mut snippets) = SnippetGenerator::create(&searcher, &*q, lyrics_text) else {
+            return SearchResults::default();
+        };
+        snippets.set_max_num_chars(160);
+
+        let mut results = SearchResults::default();
+        for (_, doc_address) in top_docs {
+            let Ok(doc) = searcher.doc::<TantivyDocument>(doc_address) else {
+                continue;
+            };
+            let Some(track) = doc
+                .get_first(path)
+                .and_then(|x| x.as_str())
+                .and_then(|x| self.get_song(x))
+            else {
+                continue;
+            };
+
+            let line = doc
+                .get_first(synced)
+                .and_then(|x| x.as_str())
+                .and_then(|x| lyrics::matching_line(x, query));
 
-        SearchResults { albums, tracks }
+            results.lyrics.push(LyricsMatch {
+                path: track.file_path.clone(),
+                snippet: snippets.snippet_from_doc(&doc).to_html(),
+                time: line.as_ref().map(|(time, _)| *time),
+                line: line.map(|(_, text)| text),
+            });
+            results.tracks.push(track);
+        }
+
+        results
     }
 
     pub fn cache(&self, cache_dir: PathBuf, win: Option<tauri::Window>) {
@@ -463,85 +608,16 @@ impl Media {
         let mut f = fs::File::create(&p_string).unwrap();
         let _ = f.write_all(&bin);
 
-        self.create_search_index(cache_dir);
-
         if let Some(win) = win {
             let _ = win.emit("synched", ());
         }
     }
 
-    pub fn create_search_index(&self, cache_dir: PathBuf) {
-        let songs_index_path = cache_dir.join(".search.songs");
-        let albums_index_path = cache_dir.join(".search.albums");
-
-        if songs_index_path.exists() {
-            let _ = std::fs::remove_dir_all(&songs_index_path);
-        }
-
-        if std::fs::DirBuilder::new()
-            .recursive(true)
-            .create(&songs_index_path)
-            .is_err()
-        {
-            return;
-        };
-
-        if albums_index_path.exists() {
-            let _ = std::fs::remove_dir_all(&albums_index_path);
-        }
-        if std::fs::DirBuilder::new()
-            .recursive(true)
-            .create(&albums_index_path)
-            .is_err()
-        {
-            return;
-        };
-
-        let mut schema_builder = tantivy::schema::Schema::builder();
-        let title = schema_builder.add_text_field("title", TEXT | STORED);
-        let artists = schema_builder.add_text_field("artists", TEXT | STORED);
-        let album = schema_builder.add_text_field("album", TEXT | STORED);
-        let path = schema_builder.add_text_field("path", TEXT | STORED);
-        let songs_schema = schema_builder.build();
-
-        schema_builder = tantivy::schema::Schema::builder();
-        let album_name = schema_builder.add_text_field("name", TEXT | STORED);
-        let album_artist = schema_builder.add_text_field("artist", TEXT | STORED);
-        let album_id = schema_builder.add_text_field("id", TEXT | STORED);
-        let album_schema = schema_builder.build();
-
-        let songs_index = Index::create_in_dir(&songs_index_path, songs_schema).unwrap();
-        let mut index_writer: IndexWriter = songs_index.writer(50_000_000).unwrap();
-        for TrackCollection(_, track) in &self.tracks {
-            let _ = index_writer.add_document(doc!(
-                title => track.title.clone(),
-                artists => track.artists.join(";"),
-                album => track.album.clone(),
-                path => track.file_path.clone(),
-            ));
-        }
-
-        let p = index_writer.prepare_commit().unwrap();
-        let _ = p.commit();
-
-        let albums_index = Index::create_in_dir(&albums_index_path, album_schema).unwrap();
-        index_writer = albums_index.writer(50_000_000).unwrap();
-        for album in &self.albums {
-            let _ = index_writer.add_document(doc!(
-                album_name => album.name.clone(),
-                album_artist => album.artist.clone(),
-                album_id => album.id.clone(),
-            ));
-        }
-
-        let p = index_writer.prepare_commit().unwrap();
-        let _ = p.commit();
-    }
-
     pub fn swap_with(&mut self, media: Media) {
         self.albums = media.albums;
         self.tracks = media.tracks;
         self.playlists = media.playlists;
+        self.palettes = media.palettes;
     }
 
     pub fn add_song(&mut self, song: Track) {
@@ -562,18 +638,92 @@ impl Media {
         }
     }
 
-    pub fn add_media(&mut self, path: PathBuf, covers_dir: &PathBuf) {
-        let ext = path.extension().unwrap().to_str().unwrap();
-        if ext == "playlist" {
-            self.add_playlist(PlaylistData::parse(format!("{}", path.display())));
-        } else if let Ok(song) = Track::from_file(covers_dir, path) {
+    pub fn add_media(
+        &mut self,
+        path: PathBuf,
+        covers_dir: &PathBuf,
+        templates: &[infer::PathTemplate],
+    ) {
+        if list::is_playlist(&path) {
+            match PlaylistData::parse(format!("{}", path.display()), self) {
+                Ok(playlist) => self.add_playlist(playlist),
+                Err(e) => warn!("Unable to read the playlist {}: {e}", path.display()),
+            }
+        } else if let Ok(mut song) = Track::from_file(covers_dir, path, templates) {
+            self.apply_palette(&mut song, covers_dir);
             self.add_song(song);
         }
     }
 
+    /// Palettes are extracted once per cover digest and shared by every track of the album.
+    fn apply_palette(&mut self, song: &mut Track, covers_dir: &std::path::Path) {
+        let cover_path = covers_dir.join(format!("{}{}", song.album_id, song.cover_ext));
+        if !self.palettes.contains_key(&song.album_id) && cover_path.exists() {
+            if let Some(palette) = Palette::from_path(&cover_path) {
+                self.palettes.insert(song.album_id.clone(), palette);
+            }
+        }
+
+        if let Some(palette) = self.palettes.get(&song.album_id) {
+            song.color = Some(palette.dominant);
+            song.is_light = Some(palette.is_light);
+        }
+    }
+
+    pub fn album_tracks(&self, album_id: &str) -> Vec<Track> {
+        self.get_album(album_id)
+            .map(|album| {
+                album
+                    .tracks
+                    .iter()
+                    .filter_map(|path| self.get_song(path))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn has_cover(&self, album_id: &str, covers_dir: &std::path::Path) -> bool {
+        self.album_tracks(album_id).first().is_some_and(|track| {
+            covers_dir
+                .join(format!("{album_id}{}", track.cover_ext))
+                .exists()
+        })
+    }
+
+    /// The previous cover and its thumbnails are removed so they stop being served.
+    pub fn set_cover(&mut self, album_id: &str, ext: &str, covers_dir: &std::path::Path) {
+        self.palettes.remove(album_id);
+        let mut tracks = self.album_tracks(album_id);
+        if let Some(old) = tracks.first().map(|x| x.cover_ext.clone()) {
+            if old != ext {
+                let _ = fs::remove_file(covers_dir.join(format!("{album_id}{old}")));
+            }
+        }
+        thumbnail::remove_thumbnails(covers_dir, album_id);
+        for track in &mut tracks {
+            track.cover_ext = ext.to_string();
+            self.apply_palette(track, covers_dir);
+            self.tracks.ninsert(track.file_path.clone(), track.clone());
+        }
+    }
+
+    pub fn get_palette(&self, album_id: &str) -> Option<Palette> {
+        self.palettes.get(album_id).copied()
+    }
+
+    /// Reads a song back from the disk, e.g. after its tags were rewritten.
+    pub fn reload_song(
+        &mut self,
+        path: &str,
+        covers_dir: &PathBuf,
+        templates: &[infer::PathTemplate],
+    ) {
+        self.remove_song(path.to_string());
+        self.add_media(PathBuf::from(path), covers_dir, templates);
+    }
+
     pub fn remove_media(&mut self, path: PathBuf) {
-        let ext = path.extension().unwrap().to_str().unwrap();
-        if ext == "playlist" {
+        if list::is_playlist(&path) {
             self.remove_playlist(format!("{}", path.display()));
         } else {
             self.remove_song(format!("{}", path.display()));
@@ -606,6 +756,35 @@ impl Media {
         }
     }
 
+    /// Evaluates the rules of the smart playlists again, they are read from
+    /// their file so edits made outside are picked up as well.
+    pub fn refresh_smart_lists(&mut self, analyzer: &mut TextAnalyzer) {
+        let mut playlists = std::mem::take(&mut self.playlists);
+        for playlist in playlists.iter_mut().filter(|x| smart::is_smart(&x.path)) {
+            match self.evaluate_smart_list(&playlist.path, analyzer) {
+                Ok((metadata, tracks)) => {
+                    playlist.metadata = metadata;
+                    playlist.tracks = tracks;
+                }
+                Err(e) => warn!("Unable to evaluate {}: {e}", playlist.path),
+            }
+        }
+        self.playlists = playlists;
+    }
+
+    /// The metadata and tracks of the smart playlist at `path` as of now.
+    pub fn evaluate_smart_list(
+        &self,
+        path: &str,
+        analyzer: &mut TextAnalyzer,
+    ) -> Result<(PlaylistMetadata, Vec<String>), String> {
+        let mut normalize = |x: &str| analyzer::normalize(analyzer, x);
+        let list = SmartList::load(path).map_err(|e| e.to_string())?;
+        let tracks = list.evaluate(self, &mut normalize).map_err(|e| e.message)?;
+
+        Ok((list.metadata, tracks))
+    }
+
     #[inline]
     pub fn remove_playlist(&mut self, path: String) {
         self.playlists.retain(|x| x.path != path);
@@ -618,6 +797,10 @@ impl Media {
 
         self.tracks.remove_entry(&path);
         self.albums.retain(|x| !x.tracks.is_empty());
+
+        let albums = &self.albums;
+        self.palettes
+            .retain(|id, _| albums.iter().any(|album| album.id == *id));
     }
 
     pub fn get_album(&self, id: &str) -> Option<Album> {
@@ -649,6 +832,115 @@ impl Media {
     pub fn get_song(&self, path: &str) -> Option<Track> {
         self.tracks.get(path).cloned()
     }
+
+    /// What a playlist of another player says about a track of the library.
+    pub fn list_entry(&self, path: &str) -> Option<ListEntry> {
+        self.tracks.get(path).map(|x| ListEntry {
+            location: x.file_path.clone(),
+            title: Some(x.title.clone()),
+            artist: (!x.artists.is_empty()).then(|| x.artists.join(", ")),
+            album: (!x.album.is_empty()).then(|| x.album.clone()),
+            duration: Some(x.duration),
+        })
+    }
+
+    /// Library paths of playlist entries. Files that aren't in the library are
+    /// looked up by title, then artist, album and duration. URLs and entries
+    /// that match nothing are kept as they are.
+    pub fn match_entries(&self, entries: &[ListEntry]) -> Vec<String> {
+        let paths: HashSet<&str> = self.tracks.iter().map(|x| x.0.as_str()).collect();
+        let mut titles: Option<HashMap<String, Vec<&Track>>> = None;
+
+        entries
+            .iter()
+            .map(|entry| {
+                let location = &entry.location;
+                let title = entry.title.as_deref().map(|x| x.trim().to_lowercase());
+                let (false, false, Some(title)) = (
+                    paths.contains(location.as_str()),
+                    list::is_url(location),
+                    title,
+                ) else {
+                    return location.clone();
+                };
+
+                let titles = titles.get_or_insert_with(|| {
+                    let mut titles: HashMap<String, Vec<&Track>> = HashMap::new();
+                    for track in self.tracks.iter().map(|x| x.track()) {
+                        titles
+                            .entry(track.title.trim().to_lowercase())
+                            .or_default()
+                            .push(track);
+                    }
+                    titles
+                });
+                titles
+                    .get(&title)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|track| Some((tag_score(entry, track)?, track)))
+                    // The first of the best ones
+                    .min_by_key(|x| std::cmp::Reverse(x.0))
+                    .map_or_else(|| location.clone(), |x| x.1.file_path.clone())
+            })
+            .collect()
+    }
+
+    /// Artists of the tracks and album artists, by lowercase name.
+    pub fn artists(&self) -> HashMap<String, Facet> {
+        facets(self.tracks.iter().map(|x| {
+            let track = x.track();
+            let names = track.artists.iter().chain(&track.album_artist);
+            (track, names.map(|x| x.as_str()).collect())
+        }))
+    }
+
+    /// Genres of the tracks, by lowercase name.
+    pub fn genres(&self) -> HashMap<String, Facet> {
+        facets(self.tracks.iter().map(|x| {
+            let track = x.track();
+            (track, track.genres.iter().map(|x| x.as_str()).collect())
+        }))
+    }
+
+    /// Both of the above, for the search index to keep.
+    pub fn facets(&self) -> Facets {
+        Facets {
+            artists: self.artists().into_iter().collect(),
+            genres: self.genres().into_iter().collect(),
+        }
+    }
+}
+
+/// Groups tracks under each of the names they come with, spelled as first seen.
+fn facets<'a>(tracks: impl Iterator<Item = (&'a Track, Vec<&'a str>)>) -> HashMap<String, Facet> {
+    let mut facets: HashMap<String, Facet> = HashMap::new();
+    for (track, names) in tracks {
+        let mut keys: Vec<String> = names
+            .iter()
+            .map(|x| x.trim())
+            .filter(|x| !x.is_empty())
+            .map(|x| x.to_lowercase())
+            .collect();
+        // An artist who is also the album artist counts once
+        keys.sort();
+        keys.dedup();
+
+        for key in keys {
+            let name = names.iter().find(|x| x.trim().to_lowercase() == key);
+            let facet = facets.entry(key).or_insert_with(|| Facet {
+                name: name.unwrap_or(&"").trim().to_string(),
+                tracks: 0,
+                albums: vec![],
+            });
+            facet.tracks += 1;
+            if !facet.albums.contains(&track.album_id) {
+                facet.albums.push(track.album_id.clone());
+            }
+        }
+    }
+
+    facets
 }
 
 pub trait MapLikeAction {
@@ -694,9 +986,163 @@ impl MapLikeAction for Vec<TrackCollection> {
 }
 
 #[derive(serde::Serialize, Debug)]
+pub struct LyricsMatch {
+    pub path: String,
+    /// HTML with the matching words in `<b>`
+    pub snippet: String,
+    pub time: Option<u64>,
+    pub line: Option<String>,
+}
+
+/// Everything a hit can point to.
+struct Lookup<'a> {
+    tracks: HashMap<&'a str, &'a Track>,
+    albums: HashMap<&'a str, &'a Album>,
+    playlists: HashMap<&'a str, &'a PlaylistData>,
+    facets: Arc<Facets>,
+}
+
+impl<'a> Lookup<'a> {
+    fn new(media: &'a Media, facets: Arc<Facets>) -> Self {
+        Self {
+            tracks: media
+                .tracks
+                .iter()
+                .map(|x| (x.track().file_path.as_str(), x.track()))
+                .collect(),
+            albums: media.albums.iter().map(|x| (x.id.as_str(), x)).collect(),
+            playlists: media
+                .playlists
+                .iter()
+                .map(|x| (x.path.as_str(), x))
+                .collect(),
+            facets,
+        }
+    }
+
+    fn has(&self, kind: HitKind, id: &str) -> bool {
+        match kind {
+            HitKind::Track => self.tracks.contains_key(id),
+            HitKind::Album => self.albums.contains_key(id),
+            HitKind::Playlist => self.playlists.contains_key(id),
+            HitKind::Artist => self.facets.artists.contains_key(id),
+            HitKind::Genre => self.facets.genres.contains_key(id),
+        }
+    }
+
+    /// Adds the item behind `hit` to its list in `results`.
+    fn push(&self, hit: &SearchHit, results: &mut SearchResults) {
+        let id = hit.id.as_str();
+        match hit.kind {
+            HitKind::Track => results.tracks.extend(self.tracks.get(id).cloned().cloned()),
+            HitKind::Album => results.albums.extend(self.albums.get(id).cloned().cloned()),
+            HitKind::Playlist => results
+                .playlists
+                .extend(self.playlists.get(id).cloned().cloned()),
+            HitKind::Artist => results.artists.extend(self.facets.artists.get(id).cloned()),
+            HitKind::Genre => results.genres.extend(self.facets.genres.get(id).cloned()),
+        }
+    }
+}
+
+/// The ids of `items` matching `group` as substrings, for when the index is not ready.
+fn scan<'a, T: Attributes + 'a>(
+    group: &Group,
+    items: impl Iterator<Item = (&'a str, &'a T)>,
+    normalize: &mut impl FnMut(&str) -> String,
+) -> Vec<&'a str> {
+    items
+        .filter(|(_, x)| group.matches(*x) && group.contains(*x, normalize))
+        .map(|(id, _)| id)
+        .collect()
+}
+
+/// Keeps the page of each kind, `counts` has the totals known from the index and
+/// the others are counted from `hits`.
+fn results(
+    hits: Hits,
+    mut counts: HashMap<HitKind, usize>,
+    lookup: &Lookup,
+    page: Page,
+    plain: bool,
+) -> SearchResults {
+    let mut results = SearchResults::default();
+    let mut seen: HashMap<HitKind, usize> = HashMap::new();
+    for hit in hits.sorted() {
+        let n = seen.entry(hit.kind).or_default();
+        let position = *n;
+        *n += 1;
+        let past = page
+            .limit(hit.kind, plain)
+            .is_some_and(|x| position >= page.offset() + x);
+        if position < page.offset() || past {
+            continue;
+        }
+
+        lookup.push(&hit, &mut results);
+        results.hits.push(hit);
+    }
+
+    for kind in HitKind::ALL.into_iter().filter(|x| page.wants(*x)) {
+        let found = seen.get(&kind).copied().unwrap_or(0);
+        counts.entry(kind).or_insert(found);
+    }
+    results.counts = counts;
+
+    results
+}
+
+/// The `key` field and score of the best `limit` documents matching `q`, all of
+/// them when `None`, with how many matched.
+fn top_keys(
+    searcher: &Searcher,
+    q: &dyn tantivy::query::Query,
+    key: Field,
+    limit: Option<usize>,
+) -> (Vec<(f32, String)>, usize) {
+    let limit = limit.unwrap_or(searcher.num_docs() as usize).max(1);
+    let (top, total) = searcher
+        .search(q, &(TopDocs::with_limit(limit), Count))
+        .unwrap_or_default();
+
+    let keys = top
+        .into_iter()
+        .filter_map(|(score, address)| {
+            let doc = searcher.doc::<TantivyDocument>(address).ok()?;
+            Some((score, doc.get_first(key)?.as_str()?.to_string()))
+        })
+        .collect();
+
+    (keys, total)
+}
+
+/// An artist or a genre, with the albums it appears on.
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct Facet {
+    pub name: String,
+    pub tracks: usize,
+    pub albums: Vec<String>,
+}
+
+/// Artists and genres of the library, worked out once per sync rather than per search.
+#[derive(Debug, Default)]
+pub struct Facets {
+    pub artists: BTreeMap<String, Facet>,
+    pub genres: BTreeMap<String, Facet>,
+}
+
+/// Each kind is sorted by relevance and holds one page of results, `hits` ranks
+/// them together and `counts` has how many matched in total.
+#[derive(serde::Serialize, Debug, Default)]
 pub struct SearchResults {
     pub albums: Vec<Album>,
     pub tracks: Vec<Track>,
+    pub playlists: Vec<PlaylistData>,
+    pub artists: Vec<Facet>,
+    pub genres: Vec<Facet>,
+    pub lyrics: Vec<LyricsMatch>,
+    pub hits: Vec<SearchHit>,
+    pub counts: HashMap<HitKind, usize>,
 }
 
 impl Songs {
@@ -733,6 +1179,40 @@ impl Songs {
     }
 }
 
+/// How well a track with the same title fits a playlist entry, `None` when the
+/// artist doesn't.
+fn tag_score(entry: &ListEntry, track: &Track) -> Option<u32> {
+    let mut score = 0;
+    if let Some(artist) = &entry.artist {
+        // Exports join several artists, `A, B` or `A feat. B`
+        let artist = artist.to_lowercase();
+        let matches = track
+            .artists
+            .iter()
+            .chain(&track.album_artist)
+            .any(|x| !x.is_empty() && artist.contains(&x.to_lowercase()));
+        if !matches {
+            return None;
+        }
+        score += 4;
+    }
+    if entry
+        .album
+        .as_ref()
+        .is_some_and(|x| x.trim().to_lowercase() == track.album.trim().to_lowercase())
+    {
+        score += 2;
+    }
+    if entry
+        .duration
+        .is_some_and(|x| x.abs_diff(track.duration) <= 2)
+    {
+        score += 1;
+    }
+
+    Some(score)
+}
+
 pub fn check_dir(dir: &PathBuf) {
     if !dir.exists() {
         fs::DirBuilder::new().recursive(true).create(dir).unwrap();
@@ -760,16 +1240,15 @@ pub mod utils {
                     if inode.is_file() {
                         let guess =
                             mime_guess::from_path(&inode).first_or("text/plain".parse().unwrap());
-                        if guess.type_() == super::mime::AUDIO
-                            || (inode.extension().is_some()
-                                && inode.extension().unwrap() == "playlist")
-                        {
+                        if super::list::is_playlist(&inode) || guess.type_() == super::mime::AUDIO {
                             files.push(inode);
                         }
                     }
                 }
             }
         }
+        // Playlists last, their entries are matched against the tracks
+        files.sort_by_key(|x| super::list::is_playlist(x));
         files
     }
 
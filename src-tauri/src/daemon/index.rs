@@ -0,0 +1,712 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::UNIX_EPOCH;
+use tantivy::collector::DocSetCollector;
+use tantivy::query::AllQuery;
+use tantivy::schema::*;
+use tantivy::tokenizer::TextAnalyzer;
+use tantivy::{doc, Index, IndexReader, IndexWriter, ReloadPolicy, Searcher, Term};
+use tracing::{error, info, warn};
+
+use super::{
+    analyzer::{Analysis, TOKENIZER},
+    global::{Album, Facets, Media, Track},
+    lyrics,
+    search::HitKind,
+    suggest::{Suggestion, Suggestions},
+};
+
+/// Bumped whenever the fields below change, the indexes are then built again.
+const SCHEMA_VERSION: u32 = 4;
+
+/// Searchable text going through the library analyser, positions are kept for phrases.
+fn analysed() -> TextOptions {
+    let indexing = TextFieldIndexing::default()
+        .set_tokenizer(TOKENIZER)
+        .set_index_option(IndexRecordOption::WithFreqsAndPositions);
+
+    TextOptions::default()
+        .set_indexing_options(indexing)
+        .set_stored()
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SongFields {
+    pub title: Field,
+    pub artists: Field,
+    pub album: Field,
+    pub path: Field,
+    pub lyrics: Field,
+    pub synced: Field,
+    hash: Field,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AlbumFields {
+    pub name: Field,
+    pub artist: Field,
+    pub id: Field,
+    hash: Field,
+}
+
+/// Playlists, artists and genres, `key` is the kind and id like `artist:muse`.
+#[derive(Debug, Clone, Copy)]
+pub struct CollectionFields {
+    pub kind: Field,
+    pub key: Field,
+    pub name: Field,
+    pub description: Field,
+    hash: Field,
+}
+
+fn songs_schema() -> (Schema, SongFields) {
+    let mut builder = Schema::builder();
+    let fields = SongFields {
+        title: builder.add_text_field("title", analysed()),
+        artists: builder.add_text_field("artists", analysed()),
+        album: builder.add_text_field("album", analysed()),
+        path: builder.add_text_field("path", STRING | STORED),
+        lyrics: builder.add_text_field("lyrics", analysed()),
+        synced: builder.add_text_field("synced", STORED),
+        hash: builder.add_text_field("hash", STORED),
+    };
+
+    (builder.build(), fields)
+}
+
+fn albums_schema() -> (Schema, AlbumFields) {
+    let mut builder = Schema::builder();
+    let fields = AlbumFields {
+        name: builder.add_text_field("name", analysed()),
+        artist: builder.add_text_field("artist", analysed()),
+        id: builder.add_text_field("id", STRING | STORED),
+        hash: builder.add_text_field("hash", STORED),
+    };
+
+    (builder.build(), fields)
+}
+
+fn collections_schema() -> (Schema, CollectionFields) {
+    let mut builder = Schema::builder();
+    let fields = CollectionFields {
+        kind: builder.add_text_field("kind", STRING),
+        key: builder.add_text_field("key", STRING | STORED),
+        name: builder.add_text_field("name", analysed()),
+        description: builder.add_text_field("description", analysed()),
+        hash: builder.add_text_field("hash", STORED),
+    };
+
+    (builder.build(), fields)
+}
+
+/// Splits a collection key back into its kind and id.
+pub fn collection_key(key: &str) -> Option<(HitKind, &str)> {
+    let (kind, id) = key.split_once(':')?;
+    let kind = HitKind::COLLECTIONS
+        .into_iter()
+        .find(|x| x.name() == kind)?;
+
+    Some((kind, id))
+}
+
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexStatus {
+    Ready,
+    Building,
+    Missing,
+    Corrupt,
+    /// Built with another schema, or an update failed half way
+    Stale,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct IndexHealth {
+    pub status: IndexStatus,
+    pub error: Option<String>,
+    pub version: u32,
+    pub analysis: String,
+    pub songs: u64,
+    pub albums: u64,
+    pub collections: u64,
+}
+
+/// Opens the index at `dir`, checking it is complete and built with `schema`.
+fn open(dir: &Path, schema: &Schema) -> Result<Index, (IndexStatus, String)> {
+    if !dir.join("meta.json").exists() {
+        return Err((IndexStatus::Missing, format!("{} not found", dir.display())));
+    }
+
+    let index = Index::open_in_dir(dir).map_err(|e| (IndexStatus::Corrupt, format!("{e}")))?;
+    if index.schema() != *schema {
+        return Err((IndexStatus::Stale, "the schema changed".to_string()));
+    }
+    match index.validate_checksum() {
+        Ok(files) if files.is_empty() => Ok(index),
+        Ok(files) => Err((
+            IndexStatus::Corrupt,
+            format!("{} damaged files in {}", files.len(), dir.display()),
+        )),
+        Err(e) => Err((IndexStatus::Corrupt, format!("{e}"))),
+    }
+}
+
+/// Starts an empty index at `dir`, dropping whatever was there.
+fn create(dir: &Path, schema: Schema) -> Result<Index, String> {
+    if dir.exists() {
+        let _ = std::fs::remove_dir_all(dir);
+    }
+    std::fs::create_dir_all(dir).map_err(|e| format!("{e}"))?;
+
+    Index::create_in_dir(dir, schema).map_err(|e| format!("{e}"))
+}
+
+fn reader(index: &Index) -> Result<IndexReader, String> {
+    index
+        .reader_builder()
+        .reload_policy(ReloadPolicy::Manual)
+        .try_into()
+        .map_err(|e| format!("{e}"))
+}
+
+/// The stored `key` and `hash` of every document.
+fn indexed(reader: &IndexReader, key: Field, hash: Field) -> HashMap<String, String> {
+    let searcher = reader.searcher();
+    let Ok(addresses) = searcher.search(&AllQuery, &DocSetCollector) else {
+        return HashMap::new();
+    };
+
+    addresses
+        .into_iter()
+        .filter_map(|address| {
+            let doc = searcher.doc::<TantivyDocument>(address).ok()?;
+            let key = doc.get_first(key)?.as_str()?.to_string();
+            let hash = doc.get_first(hash)?.as_str()?.to_string();
+            Some((key, hash))
+        })
+        .collect()
+}
+
+/// Changes when a field of the song document would, lyrics are followed through
+/// the modification time of the sidecars so they are only read when they changed.
+fn track_hash(track: &Track) -> String {
+    let mut data = format!(
+        "{}\0{}\0{}\0{}",
+        track.title,
+        track.artists.join(";"),
+        track.album,
+        track.embeded_lyrics.as_deref().unwrap_or_default()
+    );
+    for path in [
+        lyrics::sidecar_path(&track.file_path),
+        lyrics::ttml_sidecar_path(&track.file_path),
+        lyrics::plain_sidecar_path(&track.file_path),
+    ] {
+        let modified = std::fs::metadata(path)
+            .and_then(|x| x.modified())
+            .ok()
+            .and_then(|x| x.duration_since(UNIX_EPOCH).ok())
+            .map(|x| x.as_nanos())
+            .unwrap_or(0);
+        data.push_str(&format!("\0{modified}"));
+    }
+
+    format!("{:x}", md5::compute(data))
+}
+
+fn album_hash(album: &Album) -> String {
+    format!(
+        "{:x}",
+        md5::compute(format!("{}\0{}", album.name, album.artist))
+    )
+}
+
+fn collection_hash(name: &str, description: &str) -> String {
+    format!("{:x}", md5::compute(format!("{name}\0{description}")))
+}
+
+/// Open handles on the indexes, cheap to clone.
+#[derive(Clone)]
+pub struct Indexes {
+    pub songs: Index,
+    pub albums: Index,
+    pub collections: Index,
+    songs_reader: IndexReader,
+    albums_reader: IndexReader,
+    collections_reader: IndexReader,
+}
+
+impl Indexes {
+    fn new(
+        songs: Index,
+        albums: Index,
+        collections: Index,
+        analysis: &Analysis,
+    ) -> Result<Self, String> {
+        for index in [&songs, &albums, &collections] {
+            index.tokenizers().register(TOKENIZER, analysis.analyzer());
+        }
+
+        Ok(Self {
+            songs_reader: reader(&songs)?,
+            albums_reader: reader(&albums)?,
+            collections_reader: reader(&collections)?,
+            songs,
+            albums,
+            collections,
+        })
+    }
+
+    pub fn songs_searcher(&self) -> Searcher {
+        self.songs_reader.searcher()
+    }
+
+    pub fn albums_searcher(&self) -> Searcher {
+        self.albums_reader.searcher()
+    }
+
+    pub fn collections_searcher(&self) -> Searcher {
+        self.collections_reader.searcher()
+    }
+}
+
+/// Hashes of the indexed documents by path, album id and collection key.
+type Indexed = (
+    HashMap<String, String>,
+    HashMap<String, String>,
+    HashMap<String, String>,
+);
+
+/// Search indexes of the songs, albums and collections, kept open for the daemon lifetime and
+/// updated document by document as the library changes. When they can't be used
+/// they are built again in the background and searches scan the library instead.
+pub struct SearchIndex {
+    cache_dir: PathBuf,
+    analysis: Analysis,
+    pub song: SongFields,
+    pub album: AlbumFields,
+    pub collection: CollectionFields,
+    indexes: RwLock<Option<Indexes>>,
+    status: Mutex<(IndexStatus, Option<String>)>,
+    indexed: Mutex<Indexed>,
+    suggestions: RwLock<Suggestions>,
+    facets: RwLock<Arc<Facets>>,
+}
+
+impl std::fmt::Debug for SearchIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SearchIndex")
+            .field("cache_dir", &self.cache_dir)
+            .field("status", &self.status)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SearchIndex {
+    pub fn open(cache_dir: &Path, analysis: Analysis) -> Self {
+        let (songs_schema, song) = songs_schema();
+        let (albums_schema, album) = albums_schema();
+        let (collections_schema, collection) = collections_schema();
+        let version = std::fs::read_to_string(cache_dir.join(".search.version")).ok();
+
+        let songs_dir = cache_dir.join(".search.songs");
+        let opened = if !songs_dir.join("meta.json").exists() {
+            Err((IndexStatus::Missing, "no index built yet".to_string()))
+        } else if version.as_deref().map(str::trim) != Some(&version_file(&analysis)) {
+            Err((
+                IndexStatus::Stale,
+                "the schema version or the analyser settings changed".to_string(),
+            ))
+        } else {
+            open(&songs_dir, &songs_schema)
+                .and_then(|songs| {
+                    Ok((
+                        songs,
+                        open(&cache_dir.join(".search.albums"), &albums_schema)?,
+                        open(&cache_dir.join(".search.collections"), &collections_schema)?,
+                    ))
+                })
+                .and_then(|(songs, albums, collections)| {
+                    Indexes::new(songs, albums, collections, &analysis)
+                        .map_err(|e| (IndexStatus::Corrupt, e))
+                })
+        };
+
+        let (indexes, status, indexed) = match opened {
+            Ok(indexes) => {
+                let indexed = (
+                    indexed(&indexes.songs_reader, song.path, song.hash),
+                    indexed(&indexes.albums_reader, album.id, album.hash),
+                    indexed(&indexes.collections_reader, collection.key, collection.hash),
+                );
+                (Some(indexes), (IndexStatus::Ready, None), indexed)
+            }
+            Err((status, e)) => {
+                warn!("The search index has to be built again: {e}");
+                (None, (status, Some(e)), Default::default())
+            }
+        };
+
+        Self {
+            cache_dir: cache_dir.to_path_buf(),
+            analysis,
+            song,
+            album,
+            collection,
+            indexes: RwLock::new(indexes),
+            status: Mutex::new(status),
+            indexed: Mutex::new(indexed),
+            suggestions: RwLock::default(),
+            facets: RwLock::default(),
+        }
+    }
+
+    fn set_status(&self, status: IndexStatus, error: Option<String>) {
+        *self.status.lock().unwrap() = (status, error);
+    }
+
+    /// Notices an index removed from under the daemon.
+    fn status(&self) -> (IndexStatus, Option<String>) {
+        let mut status = self.status.lock().unwrap();
+        if status.0 == IndexStatus::Ready
+            && !self
+                .cache_dir
+                .join(".search.songs")
+                .join("meta.json")
+                .exists()
+        {
+            *status = (
+                IndexStatus::Missing,
+                Some("the index directory was removed".to_string()),
+            );
+        }
+
+        status.clone()
+    }
+
+    /// The indexes, `None` while they can't be searched.
+    pub fn ready(&self) -> Option<Indexes> {
+        if self.status().0 != IndexStatus::Ready {
+            return None;
+        }
+
+        self.indexes.read().unwrap().clone()
+    }
+
+    pub fn analyzer(&self) -> TextAnalyzer {
+        self.analysis.analyzer()
+    }
+
+    pub fn needs_rebuild(&self) -> bool {
+        !matches!(self.status().0, IndexStatus::Ready | IndexStatus::Building)
+    }
+
+    pub fn health(&self) -> IndexHealth {
+        let (status, error) = self.status();
+        let indexes = self.indexes.read().unwrap().clone();
+
+        IndexHealth {
+            status,
+            error,
+            version: SCHEMA_VERSION,
+            analysis: self.analysis.signature(),
+            songs: indexes
+                .as_ref()
+                .map_or(0, |x| x.songs_searcher().num_docs()),
+            albums: indexes
+                .as_ref()
+                .map_or(0, |x| x.albums_searcher().num_docs()),
+            collections: indexes
+                .as_ref()
+                .map_or(0, |x| x.collections_searcher().num_docs()),
+        }
+    }
+
+    /// Artists and genres as of the last sync.
+    pub fn facets(&self) -> Arc<Facets> {
+        self.facets.read().unwrap().clone()
+    }
+
+    /// Up to `limit` completions of `prefix`, from the library as of the last sync.
+    pub fn suggest(&self, prefix: &str, limit: usize) -> Vec<Suggestion> {
+        self.suggestions
+            .read()
+            .unwrap()
+            .lookup(prefix, &mut self.analysis.prefix_analyzer(), limit)
+    }
+
+    /// Adds, replaces and removes the documents which differ from `media`. Left to
+    /// the rebuild while the index is not ready, suggestions and facets are
+    /// refreshed either way.
+    pub fn sync(&self, media: &Media) {
+        self.sync_songs(media, None);
+    }
+
+    /// Like `sync`, only the songs at `paths` are compared so the lyrics of the rest of
+    /// the library aren't looked at. Meant for edits of single tracks.
+    pub fn sync_paths(&self, media: &Media, paths: &[String]) {
+        self.sync_songs(media, Some(paths));
+    }
+
+    fn sync_songs(&self, media: &Media, paths: Option<&[String]>) {
+        *self.suggestions.write().unwrap() =
+            Suggestions::new(media, &mut self.analysis.prefix_analyzer());
+        let facets = Arc::new(media.facets());
+        *self.facets.write().unwrap() = facets.clone();
+
+        // Taken first so a rebuild can't swap the handles in between
+        let mut indexed = self.indexed.lock().unwrap();
+        let Some(indexes) = self.ready() else {
+            return;
+        };
+
+        if let Err(e) = self.apply(&indexes, &mut indexed, media, &facets, paths) {
+            error!("Unable to update the search index: {e}");
+            self.set_status(IndexStatus::Stale, Some(e));
+        }
+    }
+
+    /// Builds both indexes from scratch with the facets of the last sync, searches
+    /// scan the library until it is done.
+    pub fn rebuild(&self, media: &Media) {
+        {
+            let mut status = self.status.lock().unwrap();
+            if status.0 == IndexStatus::Building {
+                return;
+            }
+            status.0 = IndexStatus::Building;
+        }
+        info!("Rebuilding the search index");
+
+        // Open files can't be removed on every platform. Syncs wait for the handles to
+        // be swapped, not for the whole build.
+        {
+            let _indexed = self.indexed.lock().unwrap();
+            *self.indexes.write().unwrap() = None;
+        }
+
+        let built = create(&self.cache_dir.join(".search.songs"), songs_schema().0)
+            .and_then(|songs| {
+                let albums = create(&self.cache_dir.join(".search.albums"), albums_schema().0)?;
+                let collections = create(
+                    &self.cache_dir.join(".search.collections"),
+                    collections_schema().0,
+                )?;
+                Indexes::new(songs, albums, collections, &self.analysis)
+            })
+            .and_then(|indexes| {
+                let mut fresh = Indexed::default();
+                self.apply(&indexes, &mut fresh, media, &self.facets(), None)?;
+                Ok((indexes, fresh))
+            });
+
+        match built {
+            Ok((indexes, fresh)) => {
+                let _ = std::fs::write(
+                    self.cache_dir.join(".search.version"),
+                    version_file(&self.analysis),
+                );
+                let mut indexed = self.indexed.lock().unwrap();
+                *indexed = fresh;
+                *self.indexes.write().unwrap() = Some(indexes);
+                self.set_status(IndexStatus::Ready, None);
+                drop(indexed);
+                info!("Search index rebuilt");
+            }
+            Err(e) => {
+                error!("Unable to rebuild the search index: {e}");
+                self.set_status(IndexStatus::Corrupt, Some(e));
+            }
+        }
+    }
+
+    /// Songs outside of `paths` are left as they are, when given.
+    fn apply(
+        &self,
+        indexes: &Indexes,
+        indexed: &mut Indexed,
+        media: &Media,
+        facets: &Facets,
+        paths: Option<&[String]>,
+    ) -> Result<(), String> {
+        let (songs, albums, collections) = indexed;
+
+        let f = self.song;
+        let scope: Option<HashSet<&str>> = paths.map(|x| x.iter().map(String::as_str).collect());
+        let tracks = media
+            .tracks
+            .iter()
+            .map(|x| x.track())
+            .filter(|x| {
+                scope
+                    .as_ref()
+                    .is_none_or(|scope| scope.contains(x.file_path.as_str()))
+            })
+            .map(|x| (x.file_path.as_str(), track_hash(x), x))
+            .collect();
+        update(
+            &indexes.songs,
+            &indexes.songs_reader,
+            songs,
+            f.path,
+            tracks,
+            scope.as_ref(),
+            |path, hash, track| {
+                let mut document = doc!(
+                    f.title => track.title.clone(),
+                    f.artists => track.artists.join(";"),
+                    f.album => track.album.clone(),
+                    f.path => path,
+                    f.hash => hash,
+                );
+                if let Some((plain, timed)) = lyrics::searchable(track) {
+                    document.add_text(f.lyrics, plain);
+                    if let Some(timed) = timed {
+                        document.add_text(f.synced, timed);
+                    }
+                }
+                document
+            },
+        )?;
+
+        let f = self.album;
+        let list = media
+            .albums
+            .iter()
+            .map(|x| (x.id.as_str(), album_hash(x), x))
+            .collect();
+        update(
+            &indexes.albums,
+            &indexes.albums_reader,
+            albums,
+            f.id,
+            list,
+            None,
+            |id, hash, album| {
+                doc!(
+                    f.name => album.name.clone(),
+                    f.artist => album.artist.clone(),
+                    f.id => id,
+                    f.hash => hash,
+                )
+            },
+        )?;
+
+        let f = self.collection;
+        let Facets { artists, genres } = facets;
+        let entries: Vec<(String, &str, &str)> = media
+            .playlists
+            .iter()
+            .map(|x| {
+                let meta = |key| x.metadata.get(key).map_or("", |x: &String| x.as_str());
+                (
+                    format!("{}:{}", HitKind::Playlist.name(), x.path),
+                    meta("Name"),
+                    meta("Description"),
+                )
+            })
+            .chain(artists.iter().map(|(id, x)| {
+                (
+                    format!("{}:{id}", HitKind::Artist.name()),
+                    x.name.as_str(),
+                    "",
+                )
+            }))
+            .chain(genres.iter().map(|(id, x)| {
+                (
+                    format!("{}:{id}", HitKind::Genre.name()),
+                    x.name.as_str(),
+                    "",
+                )
+            }))
+            .collect();
+        let list = entries
+            .iter()
+            .map(|(key, name, description)| {
+                (
+                    key.as_str(),
+                    collection_hash(name, description),
+                    (*name, *description),
+                )
+            })
+            .collect();
+        update(
+            &indexes.collections,
+            &indexes.collections_reader,
+            collections,
+            f.key,
+            list,
+            None,
+            |key, hash, (name, description)| {
+                let kind = key.split_once(':').map_or("", |x| x.0);
+                doc!(
+                    f.kind => kind,
+                    f.key => key,
+                    f.name => name,
+                    f.description => description,
+                    f.hash => hash,
+                )
+            },
+        )
+    }
+}
+
+fn version_file(analysis: &Analysis) -> String {
+    format!("{SCHEMA_VERSION}\n{}", analysis.signature())
+}
+
+/// Brings `index` in line with `current`, a list of `(key, hash, item)`. Only new
+/// and changed items go through `build`, keys which are gone get deleted. With a
+/// `scope`, only the keys in it can be deleted.
+fn update<T>(
+    index: &Index,
+    reader: &IndexReader,
+    indexed: &mut HashMap<String, String>,
+    key: Field,
+    current: Vec<(&str, String, T)>,
+    scope: Option<&HashSet<&str>>,
+    build: impl Fn(&str, &str, T) -> TantivyDocument,
+) -> Result<(), String> {
+    let keys: HashSet<&str> = current.iter().map(|(key, ..)| *key).collect();
+    let removed: Vec<String> = indexed
+        .keys()
+        .filter(|x| scope.is_none_or(|scope| scope.contains(String::as_str(x))))
+        .filter(|x| !keys.contains(String::as_str(x)))
+        .cloned()
+        .collect();
+    let changed: Vec<(&str, String, T)> = current
+        .into_iter()
+        .filter(|(key, hash, _)| indexed.get(*key) != Some(hash))
+        .collect();
+    if removed.is_empty() && changed.is_empty() {
+        return Ok(());
+    }
+
+    let mut writer: IndexWriter = index.writer(50_000_000).map_err(|e| format!("{e}"))?;
+    for value in &removed {
+        writer.delete_term(Term::from_field_text(key, value));
+    }
+    let mut added = vec![];
+    for (value, hash, item) in changed {
+        writer.delete_term(Term::from_field_text(key, value));
+        writer
+            .add_document(build(value, &hash, item))
+            .map_err(|e| format!("{e}"))?;
+        added.push((value.to_string(), hash));
+    }
+    writer.commit().map_err(|e| format!("{e}"))?;
+    reader.reload().map_err(|e| format!("{e}"))?;
+
+    info!(
+        "search index: {} updated, {} removed",
+        added.len(),
+        removed.len()
+    );
+    for value in removed {
+        indexed.remove(&value);
+    }
+    indexed.extend(added);
+
+    Ok(())
+}
@@ -0,0 +1,360 @@
+use std::path::{Path, PathBuf};
+
+use super::global::Track;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Artist,
+    AlbumArtist,
+    Album,
+    Title,
+    Track,
+    Disc,
+    Year,
+    Genre,
+    Ignore,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "artist" => Some(Self::Artist),
+            "albumartist" => Some(Self::AlbumArtist),
+            "album" => Some(Self::Album),
+            "title" => Some(Self::Title),
+            "track" => Some(Self::Track),
+            "disc" => Some(Self::Disc),
+            "year" => Some(Self::Year),
+            "genre" => Some(Self::Genre),
+            "ignore" => Some(Self::Ignore),
+            _ => None,
+        }
+    }
+
+    fn is_numeric(&self) -> bool {
+        matches!(self, Self::Track | Self::Disc | Self::Year)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Artist => "artists",
+            Self::AlbumArtist => "album_artist",
+            Self::Album => "album",
+            Self::Title => "title",
+            Self::Track => "track",
+            Self::Disc => "disc",
+            Self::Year => "album_year",
+            Self::Genre => "genres",
+            Self::Ignore => "",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+    Literal(String),
+    Field(Field),
+}
+
+/// A path template such as `%albumartist%/%year% - %album%/%track% - %title%`.
+/// Every `/` separated part is matched against the end of the file path under
+/// `root`, the last one against the file name without its extension.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathTemplate {
+    components: Vec<Vec<Piece>>,
+    root: PathBuf,
+}
+
+impl PathTemplate {
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut components = vec![];
+        for component in template.split('/').filter(|x| !x.is_empty()) {
+            let mut pieces = vec![];
+            let mut rest = component;
+            while let Some(start) = rest.find('%') {
+                if start > 0 {
+                    pieces.push(Piece::Literal(rest[..start].to_string()));
+                }
+                let after = &rest[start + 1..];
+                let Some(end) = after.find('%') else {
+                    return Err(format!("unclosed field in `{template}`"));
+                };
+                let name = &after[..end];
+                let Some(field) = Field::from_name(name) else {
+                    return Err(format!("unknown field `%{name}%` in `{template}`"));
+                };
+                if let Some(Piece::Field(_)) = pieces.last() {
+                    return Err(format!("`{template}` has two fields without separator"));
+                }
+                pieces.push(Piece::Field(field));
+                rest = &after[end + 1..];
+            }
+            if !rest.is_empty() {
+                pieces.push(Piece::Literal(rest.to_string()));
+            }
+            components.push(pieces);
+        }
+
+        if components.is_empty() {
+            return Err("empty path template".to_string());
+        }
+
+        Ok(Self {
+            components,
+            root: PathBuf::new(),
+        })
+    }
+
+    /// Templates match paths from `root` on, the library directory.
+    pub fn from_config(conf: Option<lorconf::Library>, root: &Path) -> Vec<Self> {
+        let default = lorconf::Library::default();
+        conf.and_then(|x| x.path_templates)
+            .or(default.path_templates)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|template| match Self::parse(template) {
+                Ok(template) => Some(Self {
+                    root: root.to_path_buf(),
+                    ..template
+                }),
+                Err(e) => {
+                    tracing::warn!("Ignoring path template: {e}");
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// `None` as well for files outside of the root or fewer folders deep than the
+    /// template, the folders above the root say nothing about the track.
+    pub fn matches(&self, path: &Path) -> Option<Vec<(Field, String)>> {
+        let path = path.strip_prefix(&self.root).ok()?;
+        let mut parts: Vec<String> = vec![];
+        if let Some(stem) = path.file_stem() {
+            parts.push(stem.to_string_lossy().to_string());
+        }
+        let mut parent = path.parent();
+        while parts.len() < self.components.len() {
+            let dir = parent?;
+            parts.push(dir.file_name()?.to_string_lossy().to_string());
+            parent = dir.parent();
+        }
+        parts.reverse();
+
+        let mut values = vec![];
+        for (pieces, part) in self.components.iter().zip(parts.iter()) {
+            if !match_pieces(pieces, part, &mut values) {
+                return None;
+            }
+        }
+
+        Some(values)
+    }
+}
+
+fn match_pieces(pieces: &[Piece], input: &str, values: &mut Vec<(Field, String)>) -> bool {
+    let Some((first, rest)) = pieces.split_first() else {
+        return input.is_empty();
+    };
+
+    match first {
+        Piece::Literal(literal) => match input.strip_prefix(literal.as_str()) {
+            Some(input) => match_pieces(rest, input, values),
+            None => false,
+        },
+        Piece::Field(field) => {
+            let candidates: Vec<usize> = if rest.is_empty() {
+                vec![input.len()]
+            } else {
+                input
+                    .char_indices()
+                    .map(|(i, _)| i)
+                    .skip(1)
+                    .chain(std::iter::once(input.len()))
+                    .collect()
+            };
+
+            for end in candidates {
+                let value = input[..end].trim();
+                if value.is_empty() {
+                    continue;
+                }
+                if field.is_numeric() && !value.chars().all(|c| c.is_ascii_digit()) {
+                    continue;
+                }
+
+                values.push((*field, value.to_string()));
+                if match_pieces(rest, &input[end..], values) {
+                    return true;
+                }
+                values.pop();
+            }
+
+            false
+        }
+    }
+}
+
+/// Fills the fields `track` has no tag for with the values of the first matching template.
+/// An untagged disc is expected to be 0, values that don't parse are left out.
+pub fn infer(track: &mut Track, path: &Path, templates: &[PathTemplate]) {
+    let Some(values) = templates.iter().find_map(|x| x.matches(path)) else {
+        return;
+    };
+
+    let default = Track::default();
+    for (field, value) in values {
+        let filled = match field {
+            Field::Artist if track.artists.is_empty() => {
+                track.artists = vec![value];
+                true
+            }
+            Field::AlbumArtist if track.album_artist.is_none() => {
+                track.album_artist = Some(value);
+                true
+            }
+            Field::Album if track.album == default.album => {
+                track.album = value;
+                true
+            }
+            Field::Title if track.title == default.title => {
+                track.title = value;
+                true
+            }
+            Field::Track if track.track == 0 => match value.parse() {
+                Ok(no) => {
+                    track.track = no;
+                    true
+                }
+                Err(_) => false,
+            },
+            Field::Disc if track.disc == 0 => match value.parse() {
+                Ok(disc) if disc > 0 => {
+                    track.disc = disc;
+                    true
+                }
+                _ => false,
+            },
+            Field::Year if track.album_year.is_none() => {
+                track.album_year = value.parse().ok();
+                track.album_year.is_some()
+            }
+            Field::Genre if track.genres.is_empty() => {
+                track.genres = vec![value];
+                true
+            }
+            _ => false,
+        };
+
+        if filled && !track.inferred.contains(&field.name().to_string()) {
+            track.inferred.push(field.name().to_string());
+        }
+    }
+
+    // Albums are grouped by their first artist, fall back on the album artist
+    if track.artists.is_empty() {
+        if let Some(album_artist) = &track.album_artist {
+            track.artists = vec![album_artist.clone()];
+            if !track.inferred.contains(&Field::Artist.name().to_string()) {
+                track.inferred.push(Field::Artist.name().to_string());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(input: &str) -> PathTemplate {
+        PathTemplate {
+            root: PathBuf::from("/music"),
+            ..PathTemplate::parse(input).unwrap()
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_templates() {
+        for input in ["", "/", "%artist", "%artist%%title%", "%composer%/%title%"] {
+            assert!(PathTemplate::parse(input).is_err(), "{input}");
+        }
+    }
+
+    type Values<'a> = Option<&'a [(Field, &'a str)]>;
+
+    #[test]
+    fn matches_paths_below_the_root() {
+        let cases: [(&str, &str, Values); 9] = [
+            (
+                "%artist%/%album%/%track% - %title%",
+                "/music/Radiohead/OK Computer/02 - Paranoid Android.flac",
+                Some(&[
+                    (Field::Artist, "Radiohead"),
+                    (Field::Album, "OK Computer"),
+                    (Field::Track, "02"),
+                    (Field::Title, "Paranoid Android"),
+                ]),
+            ),
+            (
+                "%albumartist%/%year% - %album%/%title%",
+                "/music/Björk/1997 - Homogenic/Jóga.mp3",
+                Some(&[
+                    (Field::AlbumArtist, "Björk"),
+                    (Field::Year, "1997"),
+                    (Field::Album, "Homogenic"),
+                    (Field::Title, "Jóga"),
+                ]),
+            ),
+            // Titles may hold the separator, the numeric field doesn't
+            (
+                "%track% - %title%",
+                "/music/01 - Up - Down.ogg",
+                Some(&[(Field::Track, "01"), (Field::Title, "Up - Down")]),
+            ),
+            (
+                "%ignore%/%title%",
+                "/music/Misc/Song.flac",
+                Some(&[(Field::Ignore, "Misc"), (Field::Title, "Song")]),
+            ),
+            ("%track% - %title%", "/music/Intro - Song.flac", None),
+            ("%artist%/%album%/%title%", "/music/Album/Song.flac", None),
+            ("%artist%/%title%", "/elsewhere/Artist/Song.flac", None),
+            ("%disc%-%track% %title%", "/music/A-01 Song.flac", None),
+            ("%track%. %title%", "/music/01 Song.flac", None),
+        ];
+
+        for (input, path, expected) in cases {
+            let expected = expected.map(|x| {
+                x.iter()
+                    .map(|(field, value)| (*field, value.to_string()))
+                    .collect::<Vec<_>>()
+            });
+            assert_eq!(template(input).matches(Path::new(path)), expected, "{path}");
+        }
+    }
+
+    #[test]
+    fn fills_only_untagged_fields() {
+        let templates = [template("%artist%/%disc%-%track% %title%")];
+        let path = Path::new("/music/Artist/2-07 Title.flac");
+
+        // The scan leaves an untagged disc at 0
+        let mut untagged = Track {
+            disc: 0,
+            ..Default::default()
+        };
+        infer(&mut untagged, path, &templates);
+        assert_eq!(untagged.artists, ["Artist"]);
+        assert_eq!((untagged.disc, untagged.track), (2, 7));
+        assert_eq!(untagged.title, "Title");
+        assert_eq!(untagged.inferred, ["artists", "disc", "track", "title"]);
+
+        let mut tagged = Track {
+            title: "Tagged".to_string(),
+            ..Default::default()
+        };
+        infer(&mut tagged, path, &templates);
+        assert_eq!(tagged.title, "Tagged");
+        assert!(!tagged.inferred.contains(&"title".to_string()));
+    }
+}
@@ -0,0 +1,601 @@
+use super::{
+    lrc,
+    xml::{escape, unescape},
+};
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct Word {
+    pub start: u64,
+    pub end: u64,
+    pub text: String,
+}
+
+/// A lyrics line with its word timings, in milliseconds.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct KaraokeLine {
+    pub start: u64,
+    pub end: u64,
+    pub text: String,
+    pub words: Vec<Word>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Lrc,
+    Enhanced,
+    Ttml,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "lrc" => Some(Self::Lrc),
+            "elrc" | "enhanced" => Some(Self::Enhanced),
+            "ttml" => Some(Self::Ttml),
+            _ => None,
+        }
+    }
+
+    /// `None` for plain text.
+    pub fn detect(input: &str) -> Option<Self> {
+        let trimmed = input.trim_start();
+        if trimmed.starts_with('<') && trimmed.contains("<tt") {
+            return Some(Self::Ttml);
+        }
+
+        let mut synced = false;
+        for line in input.lines() {
+            let (times, rest) = lrc::line_timestamps(line);
+            if !times.is_empty() {
+                synced = true;
+                if lrc::word_segments(rest).len() > 1 {
+                    return Some(Self::Enhanced);
+                }
+            }
+        }
+
+        synced.then_some(Self::Lrc)
+    }
+}
+
+/// Sets the missing ends: a word lasts until the next one and a line until the next line.
+fn fill_ends(lines: &mut [KaraokeLine]) {
+    lines.sort_by_key(|x| x.start);
+    for i in 0..lines.len() {
+        let next = lines.get(i + 1).map(|x| x.start);
+        let line = &mut lines[i];
+
+        for j in 0..line.words.len() {
+            if line.words[j].end == 0 {
+                line.words[j].end = line
+                    .words
+                    .get(j + 1)
+                    .map(|x| x.start)
+                    .or(next)
+                    .unwrap_or(line.words[j].start);
+            }
+        }
+
+        if line.end == 0 {
+            line.end = line
+                .words
+                .last()
+                .map(|x| x.end)
+                .or(next)
+                .unwrap_or(line.start);
+        }
+    }
+}
+
+/// Lines of an LRC or Enhanced LRC document. Offsets should be applied beforehand.
+pub fn from_lrc(input: &str) -> Vec<KaraokeLine> {
+    let mut lines = vec![];
+    for line in input.lines() {
+        let (times, rest) = lrc::line_timestamps(line);
+        let Some(&first) = times.first() else {
+            continue;
+        };
+
+        let mut words: Vec<Word> = vec![];
+        let mut end = 0;
+        for (time, text) in lrc::word_segments(rest) {
+            if text.trim().is_empty() {
+                // A trailing tag closes the last word
+                if let (Some(time), Some(last)) = (time, words.last_mut()) {
+                    last.end = time;
+                    end = time;
+                }
+                continue;
+            }
+            words.push(Word {
+                start: time.unwrap_or(first),
+                end: 0,
+                text: text.to_string(),
+            });
+        }
+
+        let timed = words.len() > 1 || lrc::word_segments(rest).len() > 1;
+        let text = words
+            .iter()
+            .map(|x| x.text.as_str())
+            .collect::<String>()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        for &time in &times {
+            // Word timings are only meaningful for lines sung once
+            let words = if timed && times.len() == 1 {
+                words.clone()
+            } else {
+                vec![]
+            };
+            lines.push(KaraokeLine {
+                start: time,
+                end: if times.len() == 1 { end } else { 0 },
+                text: text.clone(),
+                words,
+            });
+        }
+    }
+
+    fill_ends(&mut lines);
+    lines
+}
+
+/// Whether some time can't be written in centiseconds.
+fn has_millis(lines: &[KaraokeLine]) -> bool {
+    lines.iter().any(|line| {
+        [line.start, line.end]
+            .into_iter()
+            .chain(line.words.iter().flat_map(|x| [x.start, x.end]))
+            .any(|time| time % 10 != 0)
+    })
+}
+
+pub fn to_lrc(lines: &[KaraokeLine]) -> String {
+    let millis = has_millis(lines);
+    lines
+        .iter()
+        .map(|line| {
+            format!(
+                "[{}]{}",
+                lrc::format_timestamp(line.start, millis),
+                line.text
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn to_enhanced_lrc(lines: &[KaraokeLine]) -> String {
+    let millis = has_millis(lines);
+    lines
+        .iter()
+        .map(|line| {
+            let mut out = format!("[{}]", lrc::format_timestamp(line.start, millis));
+            if line.words.is_empty() {
+                out.push_str(&line.text);
+                return out;
+            }
+            for word in &line.words {
+                out.push_str(&format!(
+                    "<{}>{}",
+                    lrc::format_timestamp(word.start, millis),
+                    word.text
+                ));
+            }
+            out.truncate(out.trim_end().len());
+            out.push_str(&format!("<{}>", lrc::format_timestamp(line.end, millis)));
+            out
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Parses a TTML clock time (`00:01:02.345`, `01:02.3`) or offset time (`62.345s`, `62345ms`).
+fn ttml_time(input: &str) -> Option<u64> {
+    let input = input.trim();
+    if let Some(ms) = input.strip_suffix("ms") {
+        return ms.parse::<f64>().ok().map(|x| x as u64);
+    }
+    if let Some(seconds) = input.strip_suffix('s') {
+        return seconds.parse::<f64>().ok().map(|x| (x * 1000.0) as u64);
+    }
+
+    let mut total = 0.0;
+    for part in input.split(':') {
+        total = total * 60.0 + part.parse::<f64>().ok()?;
+    }
+
+    Some((total * 1000.0).round() as u64)
+}
+
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let mut rest = tag;
+    while let Some(i) = rest.find(name) {
+        let after = rest[i + name.len()..].trim_start();
+        let boundary = i == 0 || rest[..i].ends_with(char::is_whitespace);
+        if let (true, Some(value)) = (boundary, after.strip_prefix('=')) {
+            let value = value.trim_start();
+            let quote = value.chars().next()?;
+            let value = &value[1..];
+            return Some(value[..value.find(quote)?].to_string());
+        }
+        rest = &rest[i + name.len()..];
+    }
+
+    None
+}
+
+/// Reads the `<p>` paragraphs of a TTML document and their timed `<span>`s.
+pub fn from_ttml(input: &str) -> Vec<KaraokeLine> {
+    let mut lines = vec![];
+    let mut rest = input;
+
+    while let Some(start) = rest.find("<p") {
+        let after = &rest[start..];
+        let Some(tag_end) = after.find('>') else {
+            break;
+        };
+        let tag = &after[..tag_end];
+        let body_end = after.find("</p>").unwrap_or(after.len());
+        let body = &after[(tag_end + 1).min(body_end)..body_end];
+        rest = &after[body_end..];
+
+        if !tag.starts_with("<p ") && tag != "<p" {
+            rest = &after[2..];
+            continue;
+        }
+        let Some(begin) = attribute(tag, "begin").and_then(|x| ttml_time(&x)) else {
+            continue;
+        };
+        let end = attribute(tag, "end")
+            .and_then(|x| ttml_time(&x))
+            .unwrap_or(0);
+
+        // Words are the timed spans, anything between them is glue text
+        let mut words: Vec<Word> = vec![];
+        let mut text = String::new();
+        let mut inner = body;
+        while let Some(open) = inner.find('<') {
+            let glue = unescape(&inner[..open]);
+            text.push_str(&glue);
+            if let Some(last) = words.last_mut() {
+                last.text.push_str(&glue);
+            }
+
+            let Some(close) = inner[open..].find('>').map(|x| x + open) else {
+                break;
+            };
+            let element = &inner[open..close];
+            inner = &inner[close + 1..];
+
+            if element.starts_with("<span") {
+                let content_end = inner.find('<').unwrap_or(inner.len());
+                let content = unescape(&inner[..content_end]);
+                text.push_str(&content);
+                if let Some(start) = attribute(element, "begin").and_then(|x| ttml_time(&x)) {
+                    words.push(Word {
+                        start,
+                        end: attribute(element, "end")
+                            .and_then(|x| ttml_time(&x))
+                            .unwrap_or(0),
+                        text: content,
+                    });
+                } else if let Some(last) = words.last_mut() {
+                    last.text.push_str(&content);
+                }
+                inner = &inner[content_end..];
+            }
+        }
+        text.push_str(&unescape(inner));
+
+        lines.push(KaraokeLine {
+            start: begin,
+            end,
+            text: text.split_whitespace().collect::<Vec<_>>().join(" "),
+            words,
+        });
+    }
+
+    fill_ends(&mut lines);
+    lines
+}
+
+fn ttml_clock(time: u64) -> String {
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        time / 3_600_000,
+        (time / 60_000) % 60,
+        (time / 1000) % 60,
+        time % 1000
+    )
+}
+
+pub fn to_ttml(lines: &[KaraokeLine]) -> String {
+    let timing = if lines.iter().any(|x| !x.words.is_empty()) {
+        "Word"
+    } else {
+        "Line"
+    };
+    let mut out = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<tt xmlns=\"http://www.w3.org/ns/ttml\" xmlns:itunes=\"http://music.apple.com/lyric-ttml-internal\" itunes:timing=\"{timing}\">\n  <body>\n    <div>\n"
+    );
+
+    for line in lines {
+        out.push_str(&format!(
+            "      <p begin=\"{}\" end=\"{}\">",
+            ttml_clock(line.start),
+            ttml_clock(line.end)
+        ));
+        if line.words.is_empty() {
+            out.push_str(&escape(&line.text));
+        }
+        for word in &line.words {
+            out.push_str(&format!(
+                "<span begin=\"{}\" end=\"{}\">{}</span>",
+                ttml_clock(word.start),
+                ttml_clock(word.end),
+                escape(word.text.trim())
+            ));
+            if word.text.ends_with(char::is_whitespace) {
+                out.push(' ');
+            }
+        }
+        out.push_str("</p>\n");
+    }
+    out.push_str("    </div>\n  </body>\n</tt>\n");
+
+    out
+}
+
+/// Any supported document, an empty list for plain text.
+pub fn parse(input: &str) -> Vec<KaraokeLine> {
+    match Format::detect(input) {
+        Some(Format::Ttml) => from_ttml(input),
+        Some(_) => from_lrc(&lrc::apply_offset(input)),
+        None => vec![],
+    }
+}
+
+/// Rewrites synced lyrics in `to`, plain text and documents already in the right format are kept.
+pub fn convert(input: &str, to: Format) -> String {
+    match (Format::detect(input), to) {
+        (None, _) => input.to_string(),
+        (Some(Format::Ttml), Format::Ttml) => input.to_string(),
+        (Some(Format::Ttml), Format::Enhanced) => to_enhanced_lrc(&from_ttml(input)),
+        (Some(Format::Ttml), Format::Lrc) => to_lrc(&from_ttml(input)),
+        (Some(_), Format::Ttml) => to_ttml(&from_lrc(&lrc::apply_offset(input))),
+        (Some(_), Format::Lrc) => lrc::strip_word_tags(input),
+        (Some(_), Format::Enhanced) => input.to_string(),
+    }
+}
+
+fn sylt_terminator(encoding: u8) -> usize {
+    match encoding {
+        1 | 2 => 2,
+        _ => 1,
+    }
+}
+
+fn sylt_text(encoding: u8, data: &[u8]) -> String {
+    match encoding {
+        0 => data.iter().map(|&x| x as char).collect(),
+        1 | 2 => {
+            let (data, big_endian) = match data {
+                [0xFF, 0xFE, rest @ ..] => (rest, false),
+                [0xFE, 0xFF, rest @ ..] => (rest, true),
+                _ => (data, encoding == 2),
+            };
+            let units: Vec<u16> = data
+                .chunks_exact(2)
+                .map(|x| {
+                    if big_endian {
+                        u16::from_be_bytes([x[0], x[1]])
+                    } else {
+                        u16::from_le_bytes([x[0], x[1]])
+                    }
+                })
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => String::from_utf8_lossy(data).to_string(),
+    }
+}
+
+/// Splits a terminated string off `data`.
+fn sylt_string(encoding: u8, data: &[u8]) -> Option<(String, &[u8])> {
+    let step = sylt_terminator(encoding);
+    let end = (0..data.len())
+        .step_by(step)
+        .find(|&i| data[i..].len() >= step && data[i..i + step].iter().all(|&x| x == 0))?;
+
+    Some((sylt_text(encoding, &data[..end]), &data[end + step..]))
+}
+
+/// Reads the body of an ID3v2 `SYLT` frame. Syllables starting with a line feed open a new line,
+/// without any every syllable is a line. Timestamps in MPEG frames are not supported.
+pub fn from_sylt(data: &[u8]) -> Option<Vec<KaraokeLine>> {
+    let (&encoding, rest) = data.split_first()?;
+    let header = rest.get(..5)?;
+    if header[3] != 2 {
+        return None;
+    }
+    let (_, mut rest) = sylt_string(encoding, &rest[5..])?;
+
+    let mut entries = vec![];
+    while let Some((text, after)) = sylt_string(encoding, rest) {
+        let Some(time) = after.get(..4) else {
+            break;
+        };
+        entries.push((
+            u32::from_be_bytes([time[0], time[1], time[2], time[3]]) as u64,
+            text,
+        ));
+        rest = &after[4..];
+    }
+
+    let grouped = entries.iter().any(|(_, x)| x.starts_with(['\n', '\r']));
+    let mut lines: Vec<KaraokeLine> = vec![];
+    for (time, text) in entries {
+        let new_line = !grouped || text.starts_with(['\n', '\r']) || lines.is_empty();
+        let text = text.trim_start_matches(['\n', '\r']).to_string();
+        if new_line {
+            lines.push(KaraokeLine {
+                start: time,
+                end: 0,
+                text: String::new(),
+                words: vec![],
+            });
+        }
+        if let Some(line) = lines.last_mut() {
+            line.text.push_str(&text);
+            if grouped {
+                line.words.push(Word {
+                    start: time,
+                    end: 0,
+                    text,
+                });
+            }
+        }
+    }
+
+    for line in &mut lines {
+        line.text = line.text.split_whitespace().collect::<Vec<_>>().join(" ");
+        // A single syllable is the line itself
+        if line.words.len() <= 1 {
+            line.words.clear();
+        }
+    }
+    fill_ends(&mut lines);
+
+    Some(lines)
+}
+
+/// Builds the body of an ID3v2.4 `SYLT` frame (UTF-8 text, millisecond timestamps),
+/// one entry per word when word timings are known.
+pub fn to_sylt(lines: &[KaraokeLine]) -> Vec<u8> {
+    // encoding, language, timestamp format, content type (lyrics) and an empty description
+    let mut data = vec![3, b'X', b'X', b'X', 2, 1, 0];
+    let mut push = |time: u64, text: &str| {
+        data.extend_from_slice(text.as_bytes());
+        data.push(0);
+        data.extend_from_slice(&(time as u32).to_be_bytes());
+    };
+
+    for (i, line) in lines.iter().enumerate() {
+        let newline = if i == 0 { "" } else { "\n" };
+        if line.words.is_empty() {
+            push(line.start, &format!("{newline}{}", line.text));
+            continue;
+        }
+        for (j, word) in line.words.iter().enumerate() {
+            let prefix = if j == 0 { newline } else { "" };
+            push(word.start, &format!("{prefix}{}", word.text));
+        }
+    }
+
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENHANCED: &str = "[00:01.00]<00:01.00>Hello <00:01.50>world<00:02.00>\n[00:03.00]Bye";
+
+    fn line(start: u64, end: u64, text: &str, words: &[(u64, u64, &str)]) -> KaraokeLine {
+        KaraokeLine {
+            start,
+            end,
+            text: text.to_string(),
+            words: words
+                .iter()
+                .map(|&(start, end, text)| Word {
+                    start,
+                    end,
+                    text: text.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn detects_formats() {
+        let cases = [
+            ("Just words", None),
+            ("[ar:Artist]\nWords", None),
+            ("[00:01.00]Line", Some(Format::Lrc)),
+            (ENHANCED, Some(Format::Enhanced)),
+            (
+                "<?xml version=\"1.0\"?><tt><body/></tt>",
+                Some(Format::Ttml),
+            ),
+            ("<b>bold</b>", None),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(Format::detect(input), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn parses_ttml_times() {
+        let cases = [
+            ("00:01:02.345", Some(62_345)),
+            ("01:02.3", Some(62_300)),
+            ("62.345s", Some(62_345)),
+            ("62345ms", Some(62_345)),
+            ("7", Some(7000)),
+            ("1:xx", None),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(ttml_time(input), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn reads_word_timings() {
+        let expected = [
+            line(
+                1000,
+                2000,
+                "Hello world",
+                &[(1000, 1500, "Hello "), (1500, 2000, "world")],
+            ),
+            line(3000, 3000, "Bye", &[]),
+        ];
+        assert_eq!(from_lrc(ENHANCED), expected);
+
+        let ttml = r#"<tt><body><div>
+            <p begin="1.0s" end="2.0s"><span begin="1.0s" end="1.5s">Hello</span> <span begin="1.5s" end="2.0s">world</span></p>
+            <p begin="00:03.000">Bye &amp; <br/>go</p>
+        </div></body></tt>"#;
+        let lines = from_ttml(ttml);
+        assert_eq!(lines[0], expected[0]);
+        assert_eq!(lines[1].text, "Bye & go");
+    }
+
+    #[test]
+    fn converts_between_formats() {
+        let ttml = convert(ENHANCED, Format::Ttml);
+        assert_eq!(Format::detect(&ttml), Some(Format::Ttml));
+        assert_eq!(convert(&ttml, Format::Enhanced), ENHANCED);
+        assert_eq!(
+            convert(ENHANCED, Format::Lrc),
+            "[00:01.00]Hello world\n[00:03.00]Bye"
+        );
+        assert_eq!(convert("Plain words", Format::Ttml), "Plain words");
+    }
+
+    #[test]
+    fn sylt_frames_round_trip() {
+        // Without end times a word lasts until the next one
+        let lines = from_lrc(ENHANCED);
+        let mut expected = lines.clone();
+        expected[0].end = 3000;
+        expected[0].words[1].end = 3000;
+        assert_eq!(from_sylt(&to_sylt(&lines)), Some(expected));
+        assert_eq!(from_sylt(&[3, b'X']), None);
+    }
+}
@@ -2,13 +2,396 @@ use base64::{engine::general_purpose::URL_SAFE, Engine as _};
 use bitcode::{Decode, Encode};
 use std::{
     collections::HashMap,
-    io::{self, Read, Write},
-    path::{Path, PathBuf},
-    str::FromStr,
+    fmt::Write,
+    io,
+    path::{Component, Path, PathBuf},
 };
 
+use super::{
+    global::Media,
+    m3u, pls,
+    smart::{self, SmartList},
+    xspf,
+};
+use tracing::warn;
+
 pub type PlaylistMetadata = HashMap<String, String>;
 
+/// Playlist files found in the library, by extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListFormat {
+    Native,
+    M3u,
+    Xspf,
+    Pls,
+}
+
+impl ListFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "playlist" => Some(Self::Native),
+            "m3u" | "m3u8" => Some(Self::M3u),
+            "xspf" => Some(Self::Xspf),
+            "pls" => Some(Self::Pls),
+            _ => None,
+        }
+    }
+
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        Self::from_name(path.as_ref().extension()?.to_str()?)
+    }
+
+    /// Format of an uploaded playlist, M3U when it is only a list of paths.
+    pub fn detect(input: &str) -> Self {
+        let start = input.trim_start_matches('\u{feff}').trim_start();
+        if start.starts_with('<') {
+            Self::Xspf
+        } else if start.to_lowercase().starts_with("[playlist]") {
+            Self::Pls
+        } else {
+            Self::M3u
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Native => "playlist",
+            Self::M3u => "m3u8",
+            Self::Xspf => "xspf",
+            Self::Pls => "pls",
+        }
+    }
+
+    pub fn mime(self) -> &'static str {
+        match self {
+            Self::Native => "text/plain; charset=utf-8",
+            Self::M3u => "audio/x-mpegurl; charset=utf-8",
+            Self::Xspf => "application/xspf+xml; charset=utf-8",
+            Self::Pls => "audio/x-scpls; charset=utf-8",
+        }
+    }
+
+    pub fn read(self, input: &str) -> (PlaylistMetadata, Vec<ListEntry>) {
+        match self {
+            Self::Native => read_native(input),
+            Self::M3u => m3u::parse(input),
+            Self::Xspf => xspf::parse(input),
+            Self::Pls => pls::parse(input),
+        }
+    }
+
+    pub fn write(self, metadata: &PlaylistMetadata, entries: &[ListEntry]) -> String {
+        match self {
+            Self::Native => write_native(metadata, entries),
+            Self::M3u => m3u::write(metadata, entries),
+            Self::Xspf => xspf::write(metadata, entries),
+            Self::Pls => pls::write(entries),
+        }
+    }
+}
+
+/// `.playlist` files, other players' playlists and smart playlists.
+pub fn is_playlist<P: AsRef<Path>>(path: P) -> bool {
+    ListFormat::from_path(&path).is_some() || smart::is_smart(&path)
+}
+
+/// A track of a foreign playlist, with what the file says about it.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ListEntry {
+    pub location: String,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// Seconds
+    pub duration: Option<u64>,
+}
+
+impl ListEntry {
+    /// `Artist - Title`, how M3U and PLS players show an entry.
+    pub fn display(&self) -> Option<String> {
+        let title = self.title.as_deref()?;
+        Some(match self.artist.as_deref() {
+            Some(artist) => format!("{artist} - {title}"),
+            None => title.to_string(),
+        })
+    }
+
+    pub fn set_display(&mut self, display: &str) {
+        let (artist, title) = match display.split_once(" - ") {
+            Some((artist, title)) => (Some(artist.trim()), title.trim()),
+            None => (None, display.trim()),
+        };
+
+        self.title = (!title.is_empty()).then(|| title.to_string());
+        self.artist = artist.filter(|x| !x.is_empty()).map(str::to_string);
+    }
+}
+
+/// Whether `location` is a stream or a web page rather than a file.
+pub fn is_url(location: &str) -> bool {
+    location.split_once("://").is_some_and(|(scheme, _)| {
+        scheme.len() > 1
+            && scheme != "file"
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+    })
+}
+
+/// Absolute path of a playlist entry, relative ones start from `dir`. URLs are
+/// kept as they are.
+pub fn resolve(location: &str, dir: &Path) -> String {
+    if is_url(location) {
+        return location.to_string();
+    }
+
+    let location = match location.strip_prefix("file://") {
+        // `file:///home/..` or `file://localhost/home/..`
+        Some(rest) => percent_decode(rest.strip_prefix("localhost").unwrap_or(rest)),
+        None => location.to_string(),
+    };
+    #[cfg(not(windows))]
+    let location = location.replace('\\', "/");
+
+    let path = dir.join(location);
+    let mut resolved = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            _ => resolved.push(component),
+        }
+    }
+
+    format!("{}", resolved.display())
+}
+
+/// `path` written from `base`, both absolute, with `/` separators.
+pub fn relative_to(path: &Path, base: &Path) -> String {
+    let path: Vec<Component> = path.components().collect();
+    let base: Vec<Component> = base.components().collect();
+    let common = path.iter().zip(&base).take_while(|(x, y)| x == y).count();
+    // Nothing in common, e.g. another drive
+    if common == 0 {
+        return format!("{}", PathBuf::from_iter(&path).display());
+    }
+
+    let mut parts = vec![String::from(".."); base.len() - common];
+    parts.extend(
+        path[common..]
+            .iter()
+            .map(|x| x.as_os_str().to_string_lossy().to_string()),
+    );
+    parts.join("/")
+}
+
+pub fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = text
+            .get(i + 1..i + 3)
+            .and_then(|x| u8::from_str_radix(x, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// Escapes a path to be written in a URI, `/` are kept.
+pub fn percent_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+
+    encoded
+}
+
+/// First line of the current `.playlist` format, older files have none.
+const NATIVE_HEADER: &str = "#LORCHESTRE-PLAYLIST";
+const NATIVE_VERSION: u32 = 2;
+/// Ends the metadata, every line after it is a track.
+const NATIVE_SEPARATOR: &str = "---";
+
+/// Keeps a value on one line, `=` is escaped too so keys can hold it.
+fn escape_native(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '=' => escaped.push_str("\\="),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+fn unescape_native(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some(c) => unescaped.push(c),
+            None => unescaped.push('\\'),
+        }
+    }
+
+    unescaped
+}
+
+/// Position of the first `=` that isn't escaped.
+fn native_split(line: &str) -> Option<(&str, &str)> {
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '=' => return Some((&line[..i], &line[i + 1..])),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Reads both the versioned format and the older one, lines that can't be
+/// read are skipped.
+fn read_native(input: &str) -> (PlaylistMetadata, Vec<ListEntry>) {
+    let input = input.trim_start_matches('\u{feff}');
+    let mut lines = input.lines();
+    let version = lines
+        .next()
+        .and_then(|x| x.strip_prefix(NATIVE_HEADER))
+        .map(|x| x.trim().parse::<u32>().unwrap_or(0));
+    let Some(version) = version else {
+        return read_legacy(input);
+    };
+    if version > NATIVE_VERSION {
+        warn!("Reading a version {version} playlist, some of it may be missed");
+    }
+
+    let mut metadata = PlaylistMetadata::new();
+    let mut entries = vec![];
+    let mut in_tracks = false;
+    for (i, line) in lines.enumerate() {
+        if in_tracks {
+            if !line.is_empty() {
+                entries.push(ListEntry {
+                    location: unescape_native(line),
+                    ..Default::default()
+                });
+            }
+        } else if line == NATIVE_SEPARATOR {
+            in_tracks = true;
+        } else if let Some((key, value)) = native_split(line) {
+            metadata.insert(unescape_native(key), unescape_native(value));
+        } else if !line.trim().is_empty() {
+            warn!("Skipping line {} of a playlist, it isn't metadata", i + 2);
+        }
+    }
+
+    (metadata, entries)
+}
+
+/// `key: value` lines and a blank line before the tracks. Files edited by hand
+/// may lack the blank line, a line is then a track when it looks like a path.
+fn read_legacy(input: &str) -> (PlaylistMetadata, Vec<ListEntry>) {
+    let mut metadata = PlaylistMetadata::new();
+    let mut entries = vec![];
+    let mut in_tracks = false;
+    for (i, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            // Playlists without metadata start with the blank line
+            in_tracks |= !metadata.is_empty() || !entries.is_empty() || i == 0;
+            continue;
+        }
+
+        let is_path = line.starts_with('/')
+            || line.starts_with('\\')
+            || is_url(line)
+            || line.chars().nth(1) == Some(':');
+        match line.split_once(':') {
+            Some((key, value)) if !in_tracks && !is_path => {
+                metadata.insert(key.trim().to_string(), value.trim().to_string());
+            }
+            _ => entries.push(ListEntry {
+                location: line.to_string(),
+                ..Default::default()
+            }),
+        }
+    }
+
+    (metadata, entries)
+}
+
+fn write_native(metadata: &PlaylistMetadata, entries: &[ListEntry]) -> String {
+    let mut output = format!("{NATIVE_HEADER} {NATIVE_VERSION}\n");
+    let mut metadata: Vec<_> = metadata.iter().collect();
+    metadata.sort();
+    for (key, value) in metadata {
+        let _ = writeln!(output, "{}={}", escape_native(key), escape_native(value));
+    }
+
+    output.push_str(NATIVE_SEPARATOR);
+    output.push('\n');
+    for entry in entries {
+        let _ = writeln!(output, "{}", escape_native(&entry.location));
+    }
+
+    output
+}
+
+/// Playlists are UTF-8, older `.m3u` files are usually in Windows-1252.
+fn read_text(path: &str) -> io::Result<String> {
+    let bytes = std::fs::read(path)?;
+    Ok(match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) => encoding_rs::WINDOWS_1252.decode(e.as_bytes()).0.to_string(),
+    })
+}
+
+/// `text` in the encoding `path` was read in, UTF-8 when it doesn't fit in
+/// Windows-1252.
+fn encode_text(path: &Path, text: String) -> Vec<u8> {
+    let legacy = std::fs::read(path).is_ok_and(|x| std::str::from_utf8(&x).is_err());
+    if legacy {
+        let (encoded, _, unmappable) = encoding_rs::WINDOWS_1252.encode(&text);
+        if !unmappable {
+            return encoded.into_owned();
+        }
+    }
+
+    text.into_bytes()
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Default, Debug, Clone, Encode, Decode)]
 pub struct PlaylistData {
     pub metadata: PlaylistMetadata,
@@ -18,13 +401,18 @@ pub struct PlaylistData {
 }
 
 impl PlaylistData {
-    pub fn update(&mut self, meta: PlaylistMetadata, tracks: Vec<String>) -> io::Result<()> {
+    pub fn update(
+        &mut self,
+        meta: PlaylistMetadata,
+        tracks: Vec<String>,
+        media: &Media,
+    ) -> io::Result<()> {
         for (k, v) in meta {
             self.metadata.insert(k, v);
         }
 
         self.tracks = tracks;
-        self.save(PathBuf::from(&self.path))
+        self.save(PathBuf::from(&self.path), |x| media.list_entry(x))
     }
 
     pub fn create<P>(
@@ -36,12 +424,9 @@ impl PlaylistData {
         P: AsRef<Path>,
     {
         let playlists_dir = audio_dir.as_ref().join("Playlists");
-        if !playlists_dir.exists() {
-            std::fs::DirBuilder::new()
-                .recursive(true)
-                .create(&playlists_dir)
-                .unwrap();
-        }
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .create(&playlists_dir)?;
         let list_path = playlists_dir.join(format!("{}.playlist", uuid::Uuid::new_v4()));
         let list = PlaylistData {
             metadata,
@@ -52,11 +437,81 @@ impl PlaylistData {
 
         let base64_path = list.path_base64.clone();
 
-        list.save(list_path)?;
+        list.save(list_path, |_| None)?;
 
         Ok(base64_path)
     }
 
+    /// Saves a playlist of another player as a new playlist of the library,
+    /// relative entries start from `dir`.
+    pub fn import<P>(
+        audio_dir: P,
+        input: &str,
+        format: ListFormat,
+        name: Option<String>,
+        dir: &Path,
+        media: &Media,
+    ) -> io::Result<String>
+    where
+        P: AsRef<Path>,
+    {
+        let (mut metadata, entries) = format.read(input);
+        if let Some(name) = name {
+            metadata.insert("Name".to_string(), name);
+        }
+        if entries.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the playlist has no entries",
+            ));
+        }
+
+        let tracks = Self::locate(entries, format, dir, media);
+        Self::create(audio_dir, metadata, tracks)
+    }
+
+    /// Library paths of the entries, those that aren't in the library are
+    /// looked up by their tags.
+    fn locate(
+        mut entries: Vec<ListEntry>,
+        format: ListFormat,
+        dir: &Path,
+        media: &Media,
+    ) -> Vec<String> {
+        if format == ListFormat::Native {
+            return entries.into_iter().map(|x| x.location).collect();
+        }
+
+        for entry in &mut entries {
+            entry.location = resolve(&entry.location, dir);
+        }
+        media.match_entries(&entries)
+    }
+
+    /// The playlist written for another player, `info` describes the tracks of
+    /// the library. Paths are written from `base` when there is one.
+    pub fn export<F>(&self, format: ListFormat, info: F, base: Option<&Path>) -> String
+    where
+        F: Fn(&str) -> Option<ListEntry>,
+    {
+        let entries: Vec<ListEntry> = self
+            .tracks
+            .iter()
+            .map(|track| {
+                let location = match base {
+                    Some(base) if !is_url(track) => relative_to(Path::new(track), base),
+                    _ => track.clone(),
+                };
+                ListEntry {
+                    location,
+                    ..info(track).unwrap_or_default()
+                }
+            })
+            .collect();
+
+        format.write(&self.metadata, &entries)
+    }
+
     pub fn delete(&self) -> io::Result<()> {
         let path = Path::new(&self.path);
         if path.exists() {
@@ -68,49 +523,157 @@ impl PlaylistData {
 }
 
 impl PlaylistData {
-    pub fn parse(path: String) -> Self {
-        let mut f = std::fs::File::open(&path).unwrap();
-        let mut input = String::new();
-        let _ = f.read_to_string(&mut input);
-
-        let mut metadata = PlaylistMetadata::new();
-        let mut tracks: Vec<PathBuf> = vec![];
-        for line in input.lines() {
-            let line = line.trim();
-            if !line.is_empty() {
-                if let Some((left, right)) = line.split_once(':') {
-                    metadata.insert(left.trim().to_string(), right.trim().to_string());
-                } else {
-                    tracks.push(PathBuf::from_str(line).unwrap());
-                }
-            }
+    /// Lines that can't be read are skipped, only an unreadable file errors.
+    pub fn parse(path: String, media: &Media) -> io::Result<Self> {
+        // Tracks are filled when the rules are evaluated
+        if smart::is_smart(&path) {
+            return Ok(Self {
+                metadata: SmartList::load(&path)
+                    .map(|x| x.metadata)
+                    .unwrap_or_default(),
+                tracks: vec![],
+                path_base64: URL_SAFE.encode(path.as_bytes()),
+                path,
+            });
         }
 
-        let s_path = path.to_string();
+        let input = read_text(&path)?;
+        let format = ListFormat::from_path(&path).unwrap_or(ListFormat::Native);
+        let (mut metadata, entries) = format.read(&input);
+
+        let file = Path::new(&path);
+        if format != ListFormat::Native && !metadata.contains_key("Name") {
+            if let Some(stem) = file.file_stem() {
+                metadata.insert("Name".to_string(), stem.to_string_lossy().to_string());
+            }
+        }
 
-        Self {
+        let dir = file.parent().unwrap_or(Path::new("/"));
+        Ok(Self {
             metadata,
-            path_base64: URL_SAFE.encode(s_path.as_bytes()),
+            path_base64: URL_SAFE.encode(path.as_bytes()),
+            tracks: Self::locate(entries, format, dir, media),
             path,
-            tracks: tracks.iter().map(|p| format!("{}", p.display())).collect(),
-        }
+        })
     }
 
-    pub fn save(&self, path: PathBuf) -> io::Result<()> {
-        let mut output = Vec::new();
-        for (meta_k, meta_v) in &self.metadata {
-            writeln!(&mut output, "{}: {}", meta_k, meta_v)?;
+    /// Written in the format of its extension, other players' playlists keep
+    /// paths relative to where they are, the tags `info` has for the tracks and
+    /// their encoding.
+    pub fn save<F>(&self, path: PathBuf, info: F) -> io::Result<()>
+    where
+        F: Fn(&str) -> Option<ListEntry>,
+    {
+        // Only the name and description of smart playlists are edited here
+        if smart::is_smart(&path) {
+            let mut list = SmartList::load(&path)?;
+            list.metadata = self.metadata.clone();
+            return list.save(&path);
         }
 
-        writeln!(&mut output)?;
+        let format = ListFormat::from_path(&path).unwrap_or(ListFormat::Native);
+        let base = match format {
+            ListFormat::Native => None,
+            _ => path.parent(),
+        };
+
+        let output = self.export(format, info, base);
+        std::fs::write(&path, encode_text(&path, output))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::daemon::testing::temp_dir;
+
+    fn metadata(pairs: &[(&str, &str)]) -> PlaylistMetadata {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    /// Input, metadata and track locations.
+    type Case<'a> = (&'a str, &'a [(&'a str, &'a str)], &'a [&'a str]);
+
+    fn locations(entries: &[ListEntry]) -> Vec<&str> {
+        entries.iter().map(|x| x.location.as_str()).collect()
+    }
+
+    #[test]
+    fn reads_native_playlists() {
+        let cases: [Case; 4] = [
+            (
+                "#LORCHESTRE-PLAYLIST 2\nName=Mix\n---\n/a.flac\n\n/b.flac\n",
+                &[("Name", "Mix")],
+                &["/a.flac", "/b.flac"],
+            ),
+            (
+                "#LORCHESTRE-PLAYLIST 2\nName=A \\= B\nDescription=One\\nTwo\njunk\n---\n/c\\\\d.flac\n",
+                &[("Name", "A = B"), ("Description", "One\nTwo")],
+                &["/c\\d.flac"],
+            ),
+            (
+                "\u{feff}#LORCHESTRE-PLAYLIST 3\nKey\\=1=v\nNew=x\n---\n",
+                &[("Key=1", "v"), ("New", "x")],
+                &[],
+            ),
+            // Only metadata, the separator never comes
+            ("#LORCHESTRE-PLAYLIST 2\nName=Empty\n", &[("Name", "Empty")], &[]),
+        ];
+        for (input, expected, tracks) in cases {
+            let (meta, entries) = read_native(input);
+            assert_eq!(meta, metadata(expected), "{input}");
+            assert_eq!(locations(&entries), tracks, "{input}");
+        }
+    }
 
-        for file in &self.tracks {
-            writeln!(&mut output, "{}", file)?;
+    #[test]
+    fn reads_legacy_playlists() {
+        let cases: [Case; 5] = [
+            (
+                "Name: Mix\nDescription: Old\n\n/a.flac\n/b.flac\n",
+                &[("Name", "Mix"), ("Description", "Old")],
+                &["/a.flac", "/b.flac"],
+            ),
+            (
+                "\n/a.flac\nnot: metadata\n",
+                &[],
+                &["/a.flac", "not: metadata"],
+            ),
+            (
+                "Name: Hand edited\n/a.flac\nC:\\Music\\b.flac\nhttp://radio/stream\n",
+                &[("Name", "Hand edited")],
+                &["/a.flac", "C:\\Music\\b.flac", "http://radio/stream"],
+            ),
+            ("/only/a.flac\n", &[], &["/only/a.flac"]),
+            ("", &[], &[]),
+        ];
+        for (input, expected, tracks) in cases {
+            // Without the header the legacy reader is used
+            let (meta, entries) = read_native(input);
+            assert_eq!(meta, metadata(expected), "{input}");
+            assert_eq!(locations(&entries), tracks, "{input}");
         }
+    }
 
-        let mut f = std::fs::File::create(path)?;
-        f.write_all(&output)?;
+    #[test]
+    fn native_playlists_round_trip() {
+        let meta = metadata(&[("Name", "a=b\\c"), ("Description", "Line\r\nbreak")]);
+        let entries = vec![ListEntry {
+            location: "/music/odd=name\\.flac".to_string(),
+            ..Default::default()
+        }];
 
-        Ok(())
+        assert_eq!(read_native(&write_native(&meta, &entries)), (meta, entries));
+    }
+
+    #[test]
+    fn create_reports_an_unusable_directory() {
+        let dir = temp_dir();
+        std::fs::write(dir.path().join("Playlists"), "").unwrap();
+        let created = PlaylistData::create(dir.path(), metadata(&[]), vec![]);
+        assert!(created.is_err());
     }
 }
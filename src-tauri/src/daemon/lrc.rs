@@ -0,0 +1,351 @@
+/// Parses an LRC timestamp body such as `01:23.45`, `01:23:45` or `01:23`, in milliseconds.
+pub fn parse_timestamp(input: &str) -> Option<u64> {
+    let (minutes, rest) = input.trim().split_once(':')?;
+    let minutes: u64 = minutes.parse().ok()?;
+
+    let (seconds, fraction) = match rest.split_once(['.', ':']) {
+        Some((seconds, fraction)) => (seconds, Some(fraction)),
+        None => (rest, None),
+    };
+    let seconds: u64 = seconds.parse().ok()?;
+
+    let millis = match fraction {
+        Some(fraction) if !fraction.is_empty() && fraction.chars().all(|c| c.is_ascii_digit()) => {
+            // `.5` is 500ms, `.45` 450ms and `.456` 456ms
+            let digits = &fraction[..fraction.len().min(3)];
+            digits.parse::<u64>().ok()? * 10u64.pow(3 - digits.len() as u32)
+        }
+        Some(_) => return None,
+        None => 0,
+    };
+
+    Some(minutes * 60_000 + seconds * 1000 + millis)
+}
+
+/// Splits the leading `[mm:ss.xx]` tags of a line. A line can hold several timestamps.
+pub fn line_timestamps(line: &str) -> (Vec<u64>, &str) {
+    let mut times = vec![];
+    let mut rest = line.trim_start();
+    while let Some(inner) = rest.strip_prefix('[') {
+        let Some(end) = inner.find(']') else {
+            break;
+        };
+        match parse_timestamp(&inner[..end]) {
+            Some(time) => times.push(time),
+            None => break,
+        }
+        rest = &inner[end + 1..];
+    }
+
+    (times, rest)
+}
+
+pub fn is_synced(input: &str) -> bool {
+    input
+        .lines()
+        .any(|line| !line_timestamps(line).0.is_empty())
+}
+
+/// `mm:ss.xx`, or `mm:ss.xxx` when `millis` is set.
+pub fn format_timestamp(time: u64, millis: bool) -> String {
+    let (minutes, seconds) = (time / 60_000, (time / 1000) % 60);
+    if millis {
+        format!("{minutes:02}:{seconds:02}.{:03}", time % 1000)
+    } else {
+        format!("{minutes:02}:{seconds:02}.{:02}", (time % 1000) / 10)
+    }
+}
+
+/// Whether a line or word timestamp of `input` is written with milliseconds, e.g. `[00:12.345]`.
+fn has_millis(input: &str) -> bool {
+    input.split(['[', '<']).skip(1).any(|part| {
+        let body = part.split([']', '>']).next().unwrap_or_default();
+        parse_timestamp(body).is_some()
+            && body
+                .split_once(':')
+                .and_then(|(_, rest)| rest.split_once(['.', ':']))
+                .is_some_and(|(_, fraction)| fraction.trim().len() >= 3)
+    })
+}
+
+/// Value of the `[offset:]` header. A positive offset makes the lyrics show up sooner.
+pub fn offset(input: &str) -> Option<i64> {
+    input.lines().find_map(|line| {
+        let inner = line.trim().strip_prefix('[')?.strip_suffix(']')?;
+        let (key, value) = inner.split_once(':')?;
+        if key.trim().eq_ignore_ascii_case("offset") {
+            value.trim().trim_start_matches('+').parse().ok()
+        } else {
+            None
+        }
+    })
+}
+
+fn is_offset_header(line: &str) -> bool {
+    line.trim()
+        .strip_prefix('[')
+        .and_then(|x| x.split_once(':'))
+        .is_some_and(|(key, _)| key.trim().eq_ignore_ascii_case("offset"))
+}
+
+/// Splits the text of a timed line on its Enhanced LRC `<mm:ss.xx>` word tags.
+/// The text before the first tag comes with no time.
+pub fn word_segments(text: &str) -> Vec<(Option<u64>, &str)> {
+    let mut segments = vec![];
+    let mut time = None;
+    let mut rest = text;
+    let mut from = 0;
+
+    while let Some(start) = rest[from..].find('<').map(|x| x + from) {
+        let inner = &rest[start + 1..];
+        match inner
+            .find('>')
+            .and_then(|end| Some((end, parse_timestamp(&inner[..end])?)))
+        {
+            Some((end, next)) => {
+                segments.push((time, &rest[..start]));
+                time = Some(next);
+                rest = &inner[end + 1..];
+                from = 0;
+            }
+            None => from = start + 1,
+        }
+    }
+    segments.push((time, rest));
+
+    segments
+}
+
+/// Rewrites the line `[mm:ss.xx]` and word `<mm:ss.xx>` timestamps of a line with `map`.
+fn map_line(line: &str, millis: bool, map: &impl Fn(u64) -> u64) -> String {
+    let (times, rest) = line_timestamps(line);
+    if times.is_empty() {
+        return line.to_string();
+    }
+
+    let mut out = String::new();
+    for time in times {
+        out.push_str(&format!("[{}]", format_timestamp(map(time), millis)));
+    }
+    for (time, text) in word_segments(rest) {
+        if let Some(time) = time {
+            out.push_str(&format!("<{}>", format_timestamp(map(time), millis)));
+        }
+        out.push_str(text);
+    }
+
+    out
+}
+
+/// Drops the word tags of an Enhanced LRC document, leaving plain LRC.
+pub fn strip_word_tags(input: &str) -> String {
+    input
+        .lines()
+        .map(|line| {
+            let (times, rest) = line_timestamps(line);
+            if times.is_empty() {
+                return line.to_string();
+            }
+            let prefix = &line[..line.len() - rest.len()];
+            let text: String = word_segments(rest).into_iter().map(|(_, x)| x).collect();
+            format!(
+                "{prefix}{}",
+                text.split_whitespace().collect::<Vec<_>>().join(" ")
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn map_timestamps(input: &str, millis: bool, map: impl Fn(u64) -> u64) -> String {
+    input
+        .lines()
+        .map(|line| map_line(line, millis, &map))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Moves every timestamp by `delta` milliseconds, negative values make lyrics show up sooner.
+/// Timestamps keep their milliseconds, and get some when `delta` is finer than centiseconds.
+pub fn shift(input: &str, delta: i64) -> String {
+    let millis = delta % 10 != 0 || has_millis(input);
+    map_timestamps(input, millis, |time| (time as i64 + delta).max(0) as u64)
+}
+
+/// Bakes the `[offset:]` header into the timestamps and drops it.
+pub fn apply_offset(input: &str) -> String {
+    match offset(input) {
+        Some(offset) if offset != 0 => shift(
+            &input
+                .lines()
+                .filter(|line| !is_offset_header(line))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            -offset,
+        ),
+        _ => input.to_string(),
+    }
+}
+
+/// Applies the offset, writes every timestamp as `mm:ss.xx` (`mm:ss.xxx` when the input has
+/// milliseconds) and trims trailing spaces.
+pub fn normalize(input: &str) -> String {
+    let input = apply_offset(&input.replace("\r\n", "\n"));
+    let lines: Vec<String> = map_timestamps(&input, has_millis(&input), |time| time)
+        .lines()
+        .map(|line| line.trim_end().to_string())
+        .collect();
+
+    lines.join("\n").trim_matches('\n').to_string() + "\n"
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LintKind {
+    Unsorted,
+    Overlap,
+    Empty,
+    Invalid,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct Lint {
+    /// 1-based line number
+    pub line: usize,
+    pub kind: LintKind,
+    pub message: String,
+}
+
+pub fn lint(input: &str) -> Vec<Lint> {
+    let mut lints = vec![];
+    let mut last = None;
+    let mut seen = std::collections::HashMap::new();
+
+    for (i, line) in input.lines().enumerate() {
+        let ln = i + 1;
+        let (times, rest) = line_timestamps(line);
+
+        if times.is_empty() {
+            let looks_timed = rest
+                .strip_prefix('[')
+                .is_some_and(|x| x.starts_with(|c: char| c.is_ascii_digit()));
+            if looks_timed {
+                lints.push(Lint {
+                    line: ln,
+                    kind: LintKind::Invalid,
+                    message: format!("Unreadable timestamp in `{}`", line.trim()),
+                });
+            } else if line.trim().is_empty() {
+                lints.push(Lint {
+                    line: ln,
+                    kind: LintKind::Empty,
+                    message: "Empty line".to_string(),
+                });
+            }
+            continue;
+        }
+
+        for time in times {
+            if let Some(previous) = seen.insert(time, ln) {
+                lints.push(Lint {
+                    line: ln,
+                    kind: LintKind::Overlap,
+                    message: format!(
+                        "[{}] is already used on line {previous}",
+                        format_timestamp(time, time % 10 != 0)
+                    ),
+                });
+            } else if last.is_some_and(|last| time < last) {
+                lints.push(Lint {
+                    line: ln,
+                    kind: LintKind::Unsorted,
+                    message: format!(
+                        "[{}] comes before the previous line",
+                        format_timestamp(time, time % 10 != 0)
+                    ),
+                });
+            }
+            last = Some(last.map_or(time, |last: u64| last.max(time)));
+        }
+    }
+
+    lints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_timestamps() {
+        let cases = [
+            ("01:23.45", Some(83_450)),
+            ("01:23.456", Some(83_456)),
+            ("01:23.5", Some(83_500)),
+            ("01:23:45", Some(83_450)),
+            ("01:23", Some(83_000)),
+            (" 00:00.00 ", Some(0)),
+            ("120:00.00", Some(7_200_000)),
+            ("01:23.", None),
+            ("01:2x.45", None),
+            ("ar:Artist", None),
+            ("0123", None),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_timestamp(input), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn splits_line_and_word_timestamps() {
+        assert_eq!(
+            line_timestamps("[00:01.00][00:02.00]Twice"),
+            (vec![1000, 2000], "Twice")
+        );
+        assert_eq!(line_timestamps("[ar:Artist]"), (vec![], "[ar:Artist]"));
+        assert_eq!(
+            word_segments("Hi <00:01.50>there <bad>one"),
+            [(None, "Hi "), (Some(1500), "there <bad>one")]
+        );
+    }
+
+    #[test]
+    fn shifts_and_normalizes() {
+        let cases = [
+            ("[00:01.00]A", 500, "[00:01.50]A"),
+            ("[00:01.00]A", -2000, "[00:00.00]A"),
+            ("[00:01.00]A", 5, "[00:01.005]A"),
+            ("[00:01.000]A", 10, "[00:01.010]A"),
+            ("[00:01.00]A <00:01.50>B", 1000, "[00:02.00]A <00:02.50>B"),
+            ("[ti:Title]", 1000, "[ti:Title]"),
+        ];
+        for (input, delta, expected) in cases {
+            assert_eq!(shift(input, delta), expected, "{input} {delta}");
+        }
+
+        let input = "\r\n[offset:+500]\r\n[0:2.5]Late  \r\n\r\n";
+        assert_eq!(normalize(input), "[00:02.00]Late\n");
+        assert_eq!(offset("[offset: -250]"), Some(-250));
+    }
+
+    #[test]
+    fn strips_word_tags() {
+        let input = "[ar:Artist]\n[00:01.00]<00:01.00>Hello <00:01.50>world";
+        assert_eq!(strip_word_tags(input), "[ar:Artist]\n[00:01.00]Hello world");
+    }
+
+    #[test]
+    fn lints_every_kind() {
+        let input = "[00:02.00]B\n[00:01.00]A\n[00:02.00]Again\n\n[0x:00]Bad";
+        let kinds: Vec<(usize, LintKind)> = lint(input).iter().map(|x| (x.line, x.kind)).collect();
+        assert_eq!(
+            kinds,
+            [
+                (2, LintKind::Unsorted),
+                (3, LintKind::Overlap),
+                (4, LintKind::Empty),
+                (5, LintKind::Invalid),
+            ]
+        );
+    }
+}
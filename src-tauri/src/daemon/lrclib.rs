@@ -0,0 +1,244 @@
+use futures::future::BoxFuture;
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tracing::{info, warn};
+
+use super::{
+    config::user_agent,
+    global::{check_dir, Track},
+    lyrics_provider::{Candidate, LyricsProvider},
+    utils::now,
+};
+
+#[allow(non_snake_case)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct LrclibRecord {
+    pub id: usize,
+    pub trackName: String,
+    pub artistName: String,
+    pub albumName: Option<String>,
+    pub duration: f64,
+    pub instrumental: bool,
+    pub plainLyrics: Option<String>,
+    pub syncedLyrics: Option<String>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+struct CacheEntry {
+    fetched_at: u64,
+    records: Vec<LrclibRecord>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Lrclib {
+    name: String,
+    endpoint: String,
+    retry_after: u64,
+    cache_dir: PathBuf,
+    client: reqwest::Client,
+}
+
+impl Lrclib {
+    pub fn new(cache_dir: &Path, name: &str, endpoint: &str, retry_after: u64) -> Self {
+        Self {
+            name: name.to_string(),
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            retry_after,
+            cache_dir: cache_dir.join("lyrics").join(name),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn cache_path(&self, track: &Track) -> PathBuf {
+        let key = format!(
+            "{}\n{}\n{}\n{}",
+            track.title,
+            track.artists.join(";"),
+            track.album,
+            track.duration
+        );
+        self.cache_dir
+            .join(format!("{:x}.json", md5::compute(key.as_bytes())))
+    }
+
+    fn read_cache(&self, track: &Track) -> Option<Vec<LrclibRecord>> {
+        let buf = std::fs::read(self.cache_path(track)).ok()?;
+        let entry: CacheEntry = serde_json::from_slice(&buf).ok()?;
+
+        // Found lyrics do not change, misses are retried after a while
+        if entry.records.is_empty() && now().saturating_sub(entry.fetched_at) >= self.retry_after {
+            return None;
+        }
+
+        Some(entry.records)
+    }
+
+    fn write_cache(&self, track: &Track, records: &[LrclibRecord]) {
+        check_dir(&self.cache_dir);
+        let entry = CacheEntry {
+            fetched_at: now(),
+            records: records.to_vec(),
+        };
+        if let Ok(data) = serde_json::to_vec(&entry) {
+            let _ = std::fs::write(self.cache_path(track), data);
+        }
+    }
+
+    async fn get(
+        &self,
+        path: &str,
+        params: &[(&str, String)],
+    ) -> Result<reqwest::Response, String> {
+        let url = reqwest::Url::parse_with_params(&format!("{}{path}", self.endpoint), params)
+            .map_err(|e| format!("{e}"))?;
+
+        self.client
+            .get(url)
+            .header("User-Agent", user_agent())
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await
+            .map_err(|e| format!("{e}"))
+    }
+
+    /// Exact match on title, artist, album and duration.
+    async fn signature_lookup(&self, track: &Track) -> Result<Option<LrclibRecord>, String> {
+        let Some(artist) = track.artists.iter().find(|x| known(x)) else {
+            return Ok(None);
+        };
+        if !known(&track.album) {
+            return Ok(None);
+        }
+
+        let response = self
+            .get(
+                "/get",
+                &[
+                    ("track_name", track.title.clone()),
+                    ("artist_name", artist.clone()),
+                    ("album_name", track.album.clone()),
+                    ("duration", track.duration.to_string()),
+                ],
+            )
+            .await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(format!("{} answered {}", self.name, response.status()));
+        }
+
+        response
+            .json::<LrclibRecord>()
+            .await
+            .map(Some)
+            .map_err(|e| format!("{e}"))
+    }
+
+    async fn search(&self, track: &Track) -> Result<Vec<LrclibRecord>, String> {
+        let mut params = vec![("track_name", track.title.clone())];
+        if let Some(artist) = track.artists.iter().find(|x| known(x)) {
+            params.push(("artist_name", artist.clone()));
+        }
+
+        let response = self.get("/search", &params).await?;
+        if !response.status().is_success() {
+            return Err(format!("{} answered {}", self.name, response.status()));
+        }
+
+        response
+            .json::<Vec<LrclibRecord>>()
+            .await
+            .map_err(|e| format!("{e}"))
+    }
+
+    /// Lyrics candidates for `track`, closest duration first. Tracks without a known
+    /// title or artist are not looked up.
+    pub async fn lookup(&self, track: &Track) -> Result<Vec<LrclibRecord>, String> {
+        if !known(&track.title) || !track.artists.iter().any(|x| known(x)) {
+            return Ok(vec![]);
+        }
+        if let Some(records) = self.read_cache(track) {
+            return Ok(records);
+        }
+
+        let mut records = match self.signature_lookup(track).await {
+            Ok(Some(record)) => vec![record],
+            Ok(None) => vec![],
+            Err(e) => {
+                warn!("{} signature lookup failed: {e}", self.name);
+                vec![]
+            }
+        };
+
+        if records.is_empty() {
+            records = self.search(track).await?;
+        }
+
+        let duration = track.duration as f64;
+        records.sort_by(|a, b| {
+            (a.duration - duration)
+                .abs()
+                .total_cmp(&(b.duration - duration).abs())
+        });
+
+        info!(
+            "~ {} lyrics candidates for {} on {}",
+            records.len(),
+            track.title,
+            self.name
+        );
+        self.write_cache(track, &records);
+
+        Ok(records)
+    }
+}
+
+/// Tags missing from the file are read as `@UNKNOWN@`.
+fn known(value: &str) -> bool {
+    !value.trim().is_empty() && value != "@UNKNOWN@"
+}
+
+/// How well a record matches the track: duration first, then title and artist.
+fn confidence(track: &Track, record: &LrclibRecord) -> f32 {
+    let diff = (record.duration - track.duration as f64).abs() as f32;
+    let duration = (1.0 - diff / 10.0).max(0.0);
+    let title = record.trackName.to_lowercase() == track.title.to_lowercase();
+    let artist = track
+        .artists
+        .iter()
+        .any(|x| x.to_lowercase() == record.artistName.to_lowercase());
+
+    duration * 0.5 + if title { 0.3 } else { 0.0 } + if artist { 0.2 } else { 0.0 }
+}
+
+impl LyricsProvider for Lrclib {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn remote(&self) -> bool {
+        true
+    }
+
+    fn fetch<'a>(&'a self, track: &'a Track) -> BoxFuture<'a, Result<Vec<Candidate>, String>> {
+        Box::pin(async move {
+            Ok(self
+                .lookup(track)
+                .await?
+                .into_iter()
+                .map(|record| {
+                    let raw = if record.instrumental {
+                        None
+                    } else {
+                        record.syncedLyrics.clone().or(record.plainLyrics.clone())
+                    };
+                    Candidate::new(&self.name, confidence(track, &record), raw)
+                })
+                .collect())
+        })
+    }
+}
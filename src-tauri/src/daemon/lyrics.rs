@@ -0,0 +1,480 @@
+use std::path::{Path, PathBuf};
+use tracing::error;
+
+use super::{
+    global::Track,
+    karaoke::{self, Format, KaraokeLine},
+    lrc, tags,
+};
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LyricsKind {
+    Synced,
+    Plain,
+    Instrumental,
+}
+
+#[derive(serde::Serialize, Default)]
+pub struct TrackLyrics {
+    pub kind: Option<LyricsKind>,
+    #[serde(rename = "lyrics")]
+    pub lines: Vec<alrc::Line>,
+    pub plain: Option<String>,
+    /// Word timings, only when the source has some
+    pub karaoke: Vec<KaraokeLine>,
+    pub variants: Vec<LyricsVariant>,
+}
+
+/// A translation or romanisation of the lyrics.
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct LyricsVariant {
+    pub lang: String,
+    pub source: String,
+    /// One entry per line of the original, `None` where the variant has nothing
+    pub lines: Vec<Option<String>>,
+}
+
+impl TrackLyrics {
+    pub fn instrumental() -> Self {
+        Self {
+            kind: Some(LyricsKind::Instrumental),
+            ..Default::default()
+        }
+    }
+
+    /// Sorts raw lyrics into synced LRC, plain text or an instrumental marker.
+    /// TTML is read through its Enhanced LRC form.
+    pub fn from_text(input: &str) -> Self {
+        let converted;
+        let input = if Format::detect(input) == Some(Format::Ttml) {
+            converted = karaoke::convert(input, Format::Enhanced);
+            converted.as_str()
+        } else {
+            input
+        };
+
+        let trimmed = input.trim();
+        if trimmed.is_empty() {
+            return Self::default();
+        }
+
+        let marker = trimmed.trim_matches(['[', ']', '(', ')']).to_lowercase();
+        if marker == "instrumental" {
+            return Self::instrumental();
+        }
+
+        if lrc::is_synced(input) {
+            let input = lrc::apply_offset(input);
+            match Track::parse_lyrics(&input) {
+                Ok(parsed) if !parsed.lines.is_empty() => {
+                    let karaoke = karaoke::from_lrc(&input);
+                    let has_words = karaoke.iter().any(|x| !x.words.is_empty());
+                    return Self {
+                        kind: Some(LyricsKind::Synced),
+                        lines: parsed.lines,
+                        plain: Some(plain_text(&input)),
+                        karaoke: if has_words { karaoke } else { vec![] },
+                    };
+                }
+                Ok(_) => {}
+                Err(e) => error!("{}", e),
+            }
+        }
+
+        Self {
+            kind: Some(LyricsKind::Plain),
+            plain: Some(plain_text(input)),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kind.is_none()
+    }
+}
+
+/// The text of an LRC document without timestamps, word tags nor `[ar:...]` like headers.
+pub fn plain_text(input: &str) -> String {
+    input
+        .lines()
+        .filter_map(|line| {
+            let (times, text) = lrc::line_timestamps(line);
+            let is_header = times.is_empty()
+                && text.starts_with('[')
+                && text.trim_end().ends_with(']')
+                && text.contains(':');
+            if is_header {
+                None
+            } else {
+                let words: String = lrc::word_segments(text)
+                    .into_iter()
+                    .map(|(_, x)| x)
+                    .collect();
+                Some(words.trim().to_string())
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+pub fn read_text<P: AsRef<Path>>(path: P) -> Option<String> {
+    let path = path.as_ref();
+    if !path.exists() {
+        return None;
+    }
+
+    match std::fs::read(path).map(String::from_utf8) {
+        Ok(Ok(buf)) => Some(buf),
+        Ok(Err(e)) => {
+            error!("{}", e);
+            None
+        }
+        Err(e) => {
+            error!("{}", e);
+            None
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SaveMode {
+    #[default]
+    Sidecar,
+    Embedded,
+    Both,
+}
+
+impl SaveMode {
+    pub fn from_name(name: &str) -> Self {
+        match name.to_lowercase().as_str() {
+            "embedded" => Self::Embedded,
+            "both" => Self::Both,
+            _ => Self::Sidecar,
+        }
+    }
+
+    pub fn from_config(conf: Option<lorconf::Lyrics>) -> Self {
+        conf.and_then(|x| x.save_mode)
+            .map(|x| Self::from_name(&x))
+            .unwrap_or_default()
+    }
+
+    pub fn sidecar(&self) -> bool {
+        matches!(self, Self::Sidecar | Self::Both)
+    }
+
+    pub fn embedded(&self) -> bool {
+        matches!(self, Self::Embedded | Self::Both)
+    }
+}
+
+pub fn sidecar_path<P: AsRef<Path>>(track_path: P) -> PathBuf {
+    track_path.as_ref().with_extension("lrc")
+}
+
+pub fn plain_sidecar_path<P: AsRef<Path>>(track_path: P) -> PathBuf {
+    track_path.as_ref().with_extension("txt")
+}
+
+pub fn ttml_sidecar_path<P: AsRef<Path>>(track_path: P) -> PathBuf {
+    track_path.as_ref().with_extension("ttml")
+}
+
+pub fn write_sidecar(path: &Path, input: &str) -> Result<(), String> {
+    std::fs::write(path, input.as_bytes())
+        .map_err(|e| format!("Unable to write {}: {e}", path.display()))
+}
+
+/// What goes into the file tags, they only hold LRC.
+pub fn embedded_text(input: &str, format: Format) -> String {
+    match format {
+        Format::Ttml => karaoke::convert(input, Format::Enhanced),
+        format => karaoke::convert(input, format),
+    }
+}
+
+/// Saves the lyrics of a track according to `mode`, synced lyrics are converted to `format`.
+/// Every target is attempted even if one of them fails.
+pub fn save<P: AsRef<Path>>(
+    track_path: P,
+    input: &str,
+    mode: SaveMode,
+    format: Format,
+) -> Result<(), String> {
+    let track_path = track_path.as_ref();
+    let mut errors = vec![];
+
+    if mode.sidecar() {
+        let path = if format == Format::Ttml && Format::detect(input).is_some() {
+            ttml_sidecar_path(track_path)
+        } else {
+            sidecar_path(track_path)
+        };
+        if let Err(e) = write_sidecar(&path, &karaoke::convert(input, format)) {
+            errors.push(e);
+        }
+    }
+
+    if mode.embedded() {
+        if let Err(e) = tags::embed_lyrics(track_path, &embedded_text(input, format)) {
+            errors.push(e);
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+/// A BCP-47 shaped tag: an ISO-639 code with an optional script and region, `pt`, `ja-Latn`
+/// or `pt-BR`.
+fn is_language_tag(tag: &str) -> bool {
+    let mut parts = tag.split('-');
+    let language = parts.next().unwrap_or_default();
+    if !(2..=3).contains(&language.len()) || !language.chars().all(|c| c.is_ascii_alphabetic()) {
+        return false;
+    }
+
+    let mut script = false;
+    let mut region = false;
+    for part in parts {
+        let alpha = part.chars().all(|c| c.is_ascii_alphabetic());
+        if !script && !region && part.len() == 4 && alpha {
+            script = true;
+        } else if !region
+            && ((part.len() == 2 && alpha)
+                || (part.len() == 3 && part.chars().all(|c| c.is_ascii_digit())))
+        {
+            region = true;
+        } else {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// `song.en.lrc`, `song.ja-Latn.ttml` or `song.pt-BR.txt` next to `song.flac`, by language.
+pub fn sidecar_variants<P: AsRef<Path>>(track_path: P) -> Vec<(String, String)> {
+    let track_path = track_path.as_ref();
+    let (Some(dir), Some(stem)) = (
+        track_path.parent(),
+        track_path.file_stem().and_then(|x| x.to_str()),
+    ) else {
+        return vec![];
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+
+    let mut variants: Vec<(String, String)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let rest = name.strip_prefix(stem)?.strip_prefix('.')?;
+            let lang = ["lrc", "ttml", "txt"]
+                .iter()
+                .find_map(|ext| rest.strip_suffix(ext)?.strip_suffix('.'))?;
+            if !is_language_tag(lang) {
+                return None;
+            }
+
+            Some((lang.to_lowercase(), read_text(entry.path())?))
+        })
+        .collect();
+    variants.sort();
+
+    variants
+}
+
+/// Lines of a lyrics document with their time when synced, empty lines are kept
+/// for synced documents only.
+fn entries(input: &str) -> Vec<(Option<u64>, String)> {
+    let input = lrc::apply_offset(&karaoke::convert(input, Format::Enhanced));
+    if !lrc::is_synced(&input) {
+        return plain_text(&input)
+            .lines()
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+            .map(|x| (None, x.to_string()))
+            .collect();
+    }
+
+    let mut lines: Vec<(Option<u64>, String)> = input
+        .lines()
+        .flat_map(|line| {
+            let (times, text) = lrc::line_timestamps(line);
+            let text: String = lrc::word_segments(text)
+                .into_iter()
+                .map(|(_, x)| x)
+                .collect();
+            let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            times
+                .into_iter()
+                .map(move |time| (Some(time), text.clone()))
+        })
+        .collect();
+    lines.sort_by_key(|(time, _)| *time);
+
+    lines
+}
+
+/// Matches the lines of `variant` with the lines of `original`: by timestamp when both
+/// are synced, by order of the non-empty lines otherwise.
+pub fn align(original: &str, variant: &str) -> Vec<Option<String>> {
+    // Translations are rarely timed more than a few hundred ms away from the original
+    const TOLERANCE: u64 = 500;
+
+    let original = entries(original);
+    let variant = entries(variant);
+    let synced = |x: &[(Option<u64>, String)]| x.first().is_some_and(|(time, _)| time.is_some());
+
+    if synced(&original) && synced(&variant) {
+        return original
+            .iter()
+            .map(|(time, _)| {
+                let time = time.unwrap_or_default();
+                variant
+                    .iter()
+                    .map(|(x, text)| (x.unwrap_or_default().abs_diff(time), text))
+                    .filter(|(diff, text)| *diff <= TOLERANCE && !text.is_empty())
+                    .min_by_key(|(diff, _)| *diff)
+                    .map(|(_, text)| text.clone())
+            })
+            .collect();
+    }
+
+    let mut texts = variant
+        .into_iter()
+        .map(|(_, x)| x)
+        .filter(|x| !x.is_empty());
+    original
+        .iter()
+        .map(
+            |(_, text)| {
+                if text.is_empty() {
+                    None
+                } else {
+                    texts.next()
+                }
+            },
+        )
+        .collect()
+}
+
+/// The sidecar and embedded variants of the lyrics `original` of a track.
+pub fn variants(track: &Track, original: &str) -> Vec<LyricsVariant> {
+    let mut found: Vec<(String, String, String)> = sidecar_variants(&track.file_path)
+        .into_iter()
+        .map(|(lang, text)| (lang, "sidecar".to_string(), text))
+        .collect();
+
+    for (lang, description, text) in tags::read_uslt(&track.file_path) {
+        // The main USLT frame is what the track already shows
+        if track.embeded_lyrics.as_deref() == Some(text.as_str()) {
+            continue;
+        }
+        let lang = if description.trim().is_empty() {
+            lang
+        } else {
+            description.trim().to_lowercase()
+        };
+        found.push((lang, "embedded".to_string(), text));
+    }
+
+    found
+        .into_iter()
+        .filter(|(_, _, text)| text.trim() != original.trim())
+        .map(|(lang, source, text)| LyricsVariant {
+            lang,
+            source,
+            lines: align(original, &text),
+        })
+        .collect()
+}
+
+/// The lyrics of a track for the search index as `(plain text, synced LRC)`.
+/// Synced sidecars win over the others.
+pub fn searchable(track: &Track) -> Option<(String, Option<String>)> {
+    let mut sources: Vec<String> = [
+        sidecar_path(&track.file_path),
+        ttml_sidecar_path(&track.file_path),
+        plain_sidecar_path(&track.file_path),
+    ]
+    .iter()
+    .filter_map(read_text)
+    .chain(track.embeded_lyrics.clone())
+    .map(|x| lrc::apply_offset(&karaoke::convert(&x, Format::Enhanced)))
+    .collect();
+
+    let synced = sources.iter().position(|x| lrc::is_synced(x)).unwrap_or(0);
+    if sources.is_empty() {
+        return None;
+    }
+    let input = sources.swap_remove(synced);
+    let plain = plain_text(&input);
+
+    Some((plain, lrc::is_synced(&input).then_some(input)))
+}
+
+/// The synced line holding most of the words of `query`, the earliest one on ties.
+pub fn matching_line(input: &str, query: &str) -> Option<(u64, String)> {
+    let words: Vec<String> = query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|x| !x.is_empty())
+        .map(|x| x.to_lowercase())
+        .collect();
+
+    entries(input)
+        .into_iter()
+        .filter_map(|(time, text)| {
+            let lower = text.to_lowercase();
+            let score = words.iter().filter(|x| lower.contains(x.as_str())).count();
+            Some((score, time?, text))
+        })
+        .filter(|(score, _, _)| *score > 0)
+        .fold(None, |best: Option<(usize, u64, String)>, x| match best {
+            Some(best) if best.0 >= x.0 => Some(best),
+            _ => Some(x),
+        })
+        .map(|(_, time, text)| (time, text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sidecar_languages_are_language_tags() {
+        for tag in [
+            "en",
+            "fra",
+            "pt-BR",
+            "ja-Latn",
+            "zh-Hant-TW",
+            "es-419",
+            "EN-us",
+        ] {
+            assert!(is_language_tag(tag), "{tag}");
+        }
+        for tag in [
+            "",
+            "e",
+            "english",
+            "remix",
+            "en-",
+            "en-B",
+            "en-Latn-Latn",
+            "en-US-GB",
+            "1x",
+        ] {
+            assert!(!is_language_tag(tag), "{tag}");
+        }
+    }
+}
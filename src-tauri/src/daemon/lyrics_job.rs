@@ -0,0 +1,244 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+use tracing::{error, warn};
+
+use super::{
+    global::{check_dir, Track},
+    lyrics::LyricsKind,
+    lyrics_provider::LyricsChain,
+};
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+pub struct JobProgress {
+    pub total: usize,
+    pub done: usize,
+    pub saved: usize,
+    pub failed: usize,
+    /// Tracks that already had lyrics
+    #[serde(default)]
+    pub skipped: usize,
+    pub paused: bool,
+    pub running: bool,
+    pub current: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct JobState {
+    progress: JobProgress,
+    /// Every track of the job, the first `progress.done` are looked up
+    queue: Vec<String>,
+}
+
+pub enum Outcome {
+    Saved,
+    NotFound,
+    Failed,
+    Skipped,
+}
+
+/// Library wide lyrics fetching. The queue is written to disk when the job starts
+/// and the progress after every track, so the job carries on where it stopped
+/// after a restart.
+#[derive(Debug)]
+pub struct LyricsJob {
+    queue_path: PathBuf,
+    progress_path: PathBuf,
+    state: Mutex<JobState>,
+    /// Held while the progress is written so an older copy can't land last
+    writing: tokio::sync::Mutex<()>,
+    running: AtomicBool,
+    interval: Duration,
+    retries: u32,
+    min_confidence: f32,
+}
+
+impl LyricsJob {
+    pub fn new(cache_dir: &Path, conf: Option<lorconf::Lyrics>) -> Self {
+        let default = lorconf::Lyrics::default();
+        let conf = conf.unwrap_or(default.clone());
+        let dir = cache_dir.join("lyrics");
+        let queue_path = dir.join("job.json");
+        let progress_path = dir.join("job.progress.json");
+
+        let mut progress: JobProgress = read_json(&progress_path).unwrap_or_default();
+        progress.running = false;
+        progress.current = None;
+        let state = JobState {
+            progress,
+            queue: read_json(&queue_path).unwrap_or_default(),
+        };
+
+        Self {
+            queue_path,
+            progress_path,
+            state: Mutex::new(state),
+            writing: tokio::sync::Mutex::new(()),
+            running: AtomicBool::new(false),
+            interval: Duration::from_millis(
+                conf.fetch_interval
+                    .or(default.fetch_interval)
+                    .unwrap_or(1000),
+            ),
+            retries: conf.fetch_retries.or(default.fetch_retries).unwrap_or(0),
+            min_confidence: conf
+                .min_confidence
+                .or(default.min_confidence)
+                .unwrap_or(1.0),
+        }
+    }
+
+    async fn persist(&self) {
+        let _writing = self.writing.lock().await;
+        let data = serde_json::to_vec(&self.state.lock().unwrap().progress);
+        match data {
+            Ok(data) => write(self.progress_path.clone(), data).await,
+            Err(e) => error!("{e}"),
+        }
+    }
+
+    pub fn progress(&self) -> JobProgress {
+        let mut progress = self.state.lock().unwrap().progress.clone();
+        progress.running = self.running.load(Ordering::SeqCst);
+
+        progress
+    }
+
+    /// Replaces the queue, unless a job is already going through one.
+    pub async fn start(&self, pending: Vec<String>) -> bool {
+        if self.running.load(Ordering::SeqCst) {
+            return false;
+        }
+
+        let queue = {
+            let mut state = self.state.lock().unwrap();
+            *state = JobState {
+                progress: JobProgress {
+                    total: pending.len(),
+                    ..Default::default()
+                },
+                queue: pending,
+            };
+            serde_json::to_vec(&state.queue)
+        };
+        match queue {
+            Ok(data) => write(self.queue_path.clone(), data).await,
+            Err(e) => error!("{e}"),
+        }
+        self.persist().await;
+
+        true
+    }
+
+    pub async fn pause(&self) {
+        self.state.lock().unwrap().progress.paused = true;
+        self.persist().await;
+    }
+
+    pub async fn resume(&self) {
+        self.state.lock().unwrap().progress.paused = false;
+        self.persist().await;
+    }
+
+    /// Whether a queue left by a previous run is waiting.
+    pub fn should_resume(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        !state.progress.paused && state.progress.done < state.queue.len()
+    }
+
+    /// Only one worker goes through the queue at a time.
+    pub fn claim(&self) -> bool {
+        self.running
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    }
+
+    pub async fn release(&self) {
+        self.running.store(false, Ordering::SeqCst);
+        self.state.lock().unwrap().progress.current = None;
+        self.persist().await;
+    }
+
+    /// The next track to look up, `None` once the queue is empty or the job paused.
+    pub fn next(&self) -> Option<String> {
+        let mut state = self.state.lock().unwrap();
+        if state.progress.paused {
+            return None;
+        }
+        state.progress.current = state.queue.get(state.progress.done).cloned();
+
+        state.progress.current.clone()
+    }
+
+    pub async fn finish(&self, outcome: Outcome) {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.progress.done += 1;
+            match outcome {
+                Outcome::Saved => state.progress.saved += 1,
+                Outcome::NotFound => {}
+                Outcome::Failed => state.progress.failed += 1,
+                Outcome::Skipped => state.progress.skipped += 1,
+            }
+        }
+        self.persist().await;
+    }
+
+    pub async fn wait(&self) {
+        tokio::time::sleep(self.interval).await;
+    }
+
+    /// The best match above the confidence threshold, retrying with a growing
+    /// delay while the providers fail.
+    pub async fn lookup(
+        &self,
+        chain: &LyricsChain,
+        track: &Track,
+    ) -> Result<Option<String>, String> {
+        let mut attempt = 0;
+        loop {
+            match chain.search(track).await {
+                Ok(candidates) => {
+                    return Ok(candidates
+                        .into_iter()
+                        .find(|x| x.confidence >= self.min_confidence)
+                        .map(|x| match x.lyrics.kind {
+                            Some(LyricsKind::Instrumental) => "[Instrumental]".to_string(),
+                            _ => x.raw,
+                        }));
+                }
+                Err(e) if attempt < self.retries => {
+                    attempt += 1;
+                    warn!("Lyrics lookup of {} failed ({attempt}): {e}", track.title);
+                    tokio::time::sleep(self.interval * 2u32.pow(attempt)).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Option<T> {
+    let buf = std::fs::read(path).ok()?;
+    serde_json::from_slice(&buf).ok()
+}
+
+/// Writes `data` to `path` off the runtime.
+async fn write(path: PathBuf, data: Vec<u8>) {
+    let written = tokio::task::spawn_blocking(move || {
+        if let Some(parent) = path.parent() {
+            check_dir(&parent.to_path_buf());
+        }
+        std::fs::write(&path, data)
+    })
+    .await;
+
+    if let Ok(Err(e)) = written {
+        error!("Unable to save the lyrics job: {e}");
+    }
+}
@@ -0,0 +1,400 @@
+use futures::future::BoxFuture;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use super::{
+    global::Track,
+    karaoke,
+    lrclib::Lrclib,
+    lyrics::{self, LyricsKind, TrackLyrics},
+    tags,
+};
+
+/// Lyrics found by a provider. `raw` is empty for instrumentals.
+#[derive(serde::Serialize)]
+pub struct Candidate {
+    pub provider: String,
+    pub confidence: f32,
+    pub raw: String,
+    pub lyrics: TrackLyrics,
+    /// The file it was read from, if any
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+}
+
+impl Candidate {
+    pub fn new(provider: &str, confidence: f32, raw: Option<String>) -> Self {
+        let lyrics = match &raw {
+            Some(raw) => TrackLyrics::from_text(raw),
+            None => TrackLyrics::instrumental(),
+        };
+
+        Self {
+            provider: provider.to_string(),
+            confidence,
+            raw: raw.unwrap_or_default(),
+            lyrics,
+            path: None,
+        }
+    }
+}
+
+pub trait LyricsProvider: std::fmt::Debug + Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Remote providers are only queried on demand.
+    fn remote(&self) -> bool {
+        false
+    }
+
+    fn fetch<'a>(&'a self, track: &'a Track) -> BoxFuture<'a, Result<Vec<Candidate>, String>>;
+}
+
+/// Reads the files that exist off the runtime.
+async fn from_files(provider: &str, confidence: f32, paths: Vec<PathBuf>) -> Vec<Candidate> {
+    let texts = tokio::task::spawn_blocking(move || {
+        paths
+            .into_iter()
+            .filter_map(|path| Some((lyrics::read_text(&path)?, path)))
+            .collect::<Vec<_>>()
+    })
+    .await
+    .unwrap_or_default();
+
+    texts
+        .into_iter()
+        .map(|(text, path)| Candidate {
+            path: Some(path),
+            ..Candidate::new(provider, confidence, Some(text))
+        })
+        .collect()
+}
+
+/// `.lrc`, `.ttml` and `.txt` files next to the track.
+#[derive(Debug)]
+pub struct Sidecar;
+
+impl LyricsProvider for Sidecar {
+    fn name(&self) -> &str {
+        "sidecar"
+    }
+
+    fn fetch<'a>(&'a self, track: &'a Track) -> BoxFuture<'a, Result<Vec<Candidate>, String>> {
+        let paths = vec![
+            lyrics::sidecar_path(&track.file_path),
+            lyrics::ttml_sidecar_path(&track.file_path),
+            lyrics::plain_sidecar_path(&track.file_path),
+        ];
+        Box::pin(async move { Ok(from_files(self.name(), 1.0, paths).await) })
+    }
+}
+
+/// Lyrics read from the file tags during the scan, and the `SYLT` frame of MP3 files
+/// which comes first as it may hold word timings.
+#[derive(Debug)]
+pub struct Embedded;
+
+impl LyricsProvider for Embedded {
+    fn name(&self) -> &str {
+        "embedded"
+    }
+
+    fn fetch<'a>(&'a self, track: &'a Track) -> BoxFuture<'a, Result<Vec<Candidate>, String>> {
+        Box::pin(async move {
+            let path = track.file_path.clone();
+            let sylt = tokio::task::spawn_blocking(move || tags::read_sylt(path))
+                .await
+                .ok()
+                .flatten()
+                .and_then(|data| karaoke::from_sylt(&data))
+                .filter(|lines| !lines.is_empty())
+                .map(|lines| karaoke::to_enhanced_lrc(&lines));
+
+            Ok(sylt
+                .iter()
+                .chain(track.embeded_lyrics.iter())
+                .map(|text| Candidate::new(self.name(), 1.0, Some(text.clone())))
+                .collect())
+        })
+    }
+}
+
+/// A lyrics directory shared between tracks, laid out as `<artist>/<title>.lrc`
+/// or `<artist> - <title>.lrc`.
+#[derive(Debug)]
+pub struct LocalDir {
+    root: PathBuf,
+}
+
+impl LocalDir {
+    pub fn new(root: &str) -> Self {
+        let root = match (root.strip_prefix("~/"), dirs::home_dir()) {
+            (Some(rest), Some(home)) => home.join(rest),
+            _ => PathBuf::from(root),
+        };
+
+        Self { root }
+    }
+
+    fn paths(&self, track: &Track) -> Vec<PathBuf> {
+        let title = sanitize(&track.title);
+        let mut artists: Vec<&String> = track.artists.iter().collect();
+        if let Some(album_artist) = &track.album_artist {
+            artists.push(album_artist);
+        }
+
+        let mut paths = vec![];
+        for artist in artists {
+            let artist = sanitize(artist);
+            for ext in ["lrc", "txt"] {
+                paths.push(self.root.join(&artist).join(format!("{title}.{ext}")));
+                paths.push(self.root.join(format!("{artist} - {title}.{ext}")));
+            }
+        }
+
+        paths
+    }
+}
+
+fn sanitize(name: &str) -> String {
+    name.replace(['/', '\\'], "_")
+}
+
+impl LyricsProvider for LocalDir {
+    fn name(&self) -> &str {
+        "local"
+    }
+
+    fn fetch<'a>(&'a self, track: &'a Track) -> BoxFuture<'a, Result<Vec<Candidate>, String>> {
+        // Matched by name only, a file next to the track is more reliable
+        Box::pin(async move { Ok(from_files(self.name(), 0.9, self.paths(track)).await) })
+    }
+}
+
+#[derive(Debug)]
+pub struct LyricsChain {
+    providers: Vec<Box<dyn LyricsProvider>>,
+}
+
+impl LyricsChain {
+    pub fn from_config(cache_dir: &Path, conf: Option<lorconf::Lyrics>) -> Self {
+        let default = lorconf::Lyrics::default();
+        let conf = conf.unwrap_or(default.clone());
+        let retry_after = conf.retry_after.or(default.retry_after).unwrap_or(0);
+        let sources = conf.sources.unwrap_or_default();
+
+        let mut providers: Vec<Box<dyn LyricsProvider>> = vec![];
+        for name in conf.providers.or(default.providers).unwrap_or_default() {
+            match name.as_str() {
+                "sidecar" => providers.push(Box::new(Sidecar)),
+                "embedded" => providers.push(Box::new(Embedded)),
+                "local" => {
+                    if let Some(root) = &conf.local_dir {
+                        providers.push(Box::new(LocalDir::new(root)));
+                    }
+                }
+                "lrclib" => {
+                    let endpoint = conf
+                        .lrclib_endpoint
+                        .clone()
+                        .or(default.lrclib_endpoint.clone())
+                        .unwrap_or_default();
+                    providers.push(Box::new(Lrclib::new(
+                        cache_dir,
+                        "lrclib",
+                        &endpoint,
+                        retry_after,
+                    )));
+                }
+                name => match sources.iter().find(|x| x.name == name) {
+                    Some(source) => providers.push(Box::new(Lrclib::new(
+                        cache_dir,
+                        &source.name,
+                        &source.endpoint,
+                        retry_after,
+                    ))),
+                    None => warn!("Unknown lyrics provider {name}"),
+                },
+            }
+        }
+
+        Self { providers }
+    }
+
+    /// Fails only when every queried provider failed.
+    async fn collect(&self, track: &Track, remote: bool) -> Result<Vec<Candidate>, String> {
+        let mut found = vec![];
+        let mut errors = vec![];
+        let mut queried = 0;
+        for provider in self.providers.iter().filter(|x| x.remote() == remote) {
+            queried += 1;
+            match provider.fetch(track).await {
+                Ok(candidates) => {
+                    found.extend(candidates.into_iter().filter(|x| !x.lyrics.is_empty()))
+                }
+                Err(e) => {
+                    warn!("{} lyrics provider failed: {e}", provider.name());
+                    errors.push(format!("{}: {e}", provider.name()));
+                }
+            }
+        }
+
+        if queried > 0 && errors.len() == queried {
+            return Err(errors.join("\n"));
+        }
+
+        Ok(found)
+    }
+
+    /// The lyrics available offline, synced ones win over plain text.
+    pub async fn best_local(&self, track: &Track) -> Option<Candidate> {
+        let mut found = self.collect(track, false).await.unwrap_or_default();
+        let synced = found
+            .iter()
+            .position(|x| x.lyrics.kind == Some(LyricsKind::Synced))
+            .unwrap_or(0);
+
+        if found.is_empty() {
+            None
+        } else {
+            Some(found.swap_remove(synced))
+        }
+    }
+
+    pub async fn local(&self, track: &Track) -> TrackLyrics {
+        self.best_local(track)
+            .await
+            .map(|x| x.lyrics)
+            .unwrap_or_default()
+    }
+
+    /// Candidates of the remote providers, most confident first.
+    pub async fn search(&self, track: &Track) -> Result<Vec<Candidate>, String> {
+        let mut found = self.collect(track, true).await?;
+        found.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::daemon::{
+        global::check_dir,
+        lyrics_job::LyricsJob,
+        testing::{self, serve, temp_dir},
+    };
+    use axum::{http::StatusCode, routing::get, Json, Router};
+
+    const SYNCED: &str = "[00:01.00]Hello\n[00:02.00]World\n";
+
+    fn track(dir: &Path) -> Track {
+        Track {
+            duration: 200,
+            file_path: format!("{}", dir.join("song.flac").display()),
+            embeded_lyrics: Some("Embedded words".to_string()),
+            ..testing::track("Song", "Artist", "Album")
+        }
+    }
+
+    fn record(id: usize, artist: &str, duration: f64, lyrics: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": id,
+            "trackName": "Song",
+            "artistName": artist,
+            "albumName": "Album",
+            "duration": duration,
+            "instrumental": false,
+            "plainLyrics": null,
+            "syncedLyrics": lyrics,
+        })
+    }
+
+    /// LRCLIB stand-in without signature matches, its search answers `records`.
+    async fn stub(records: Vec<serde_json::Value>) -> String {
+        let app = Router::new()
+            .route("/get", get(|| async { StatusCode::NOT_FOUND }))
+            .route(
+                "/search",
+                get(move || std::future::ready(Json(records.clone()))),
+            );
+
+        serve(app).await
+    }
+
+    fn conf(dir: &Path, endpoint: &str, providers: &[&str]) -> lorconf::Lyrics {
+        lorconf::Lyrics {
+            lrclib_endpoint: Some(endpoint.to_string()),
+            providers: Some(providers.iter().map(|x| x.to_string()).collect()),
+            local_dir: Some(format!("{}", dir.join("Lyrics").display())),
+            fetch_interval: Some(0),
+            fetch_retries: Some(0),
+            min_confidence: Some(0.8),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn local_providers_follow_the_configured_order() {
+        let temp = temp_dir();
+        let dir = temp.path();
+        let track = track(dir);
+        std::fs::write(lyrics::sidecar_path(&track.file_path), SYNCED).unwrap();
+        check_dir(&dir.join("Lyrics").join("Artist"));
+        std::fs::write(dir.join("Lyrics/Artist/Song.txt"), "Local words").unwrap();
+
+        let providers = ["local", "embedded", "lrclib", "sidecar"];
+        let conf = conf(dir, "http://127.0.0.1:9", &providers);
+        let chain = LyricsChain::from_config(dir, Some(conf));
+
+        let found = chain.collect(&track, false).await.unwrap();
+        let names: Vec<&str> = found.iter().map(|x| x.provider.as_str()).collect();
+        assert_eq!(names, ["local", "embedded", "sidecar"]);
+        assert_eq!(found[0].confidence, 0.9);
+        let source = dir.join("Lyrics/Artist/Song.txt");
+        assert_eq!(found[0].path.as_deref(), Some(source.as_path()));
+
+        // Synced lyrics win even from the last provider
+        let best = chain.best_local(&track).await.unwrap();
+        assert_eq!(best.provider, "sidecar");
+        assert_eq!(best.lyrics.kind, Some(LyricsKind::Synced));
+    }
+
+    #[tokio::test]
+    async fn remote_candidates_are_ranked_and_filtered() {
+        let temp = temp_dir();
+        let dir = temp.path();
+        let track = track(dir);
+        let endpoint = stub(vec![
+            record(1, "Someone Else", 260.0, "[00:01.00]Cover"),
+            record(2, "Artist", 200.0, SYNCED),
+        ])
+        .await;
+        let conf = conf(dir, &endpoint, &["sidecar", "lrclib"]);
+        let chain = LyricsChain::from_config(dir, Some(conf.clone()));
+
+        let found = chain.search(&track).await.unwrap();
+        let raws: Vec<&str> = found.iter().map(|x| x.raw.as_str()).collect();
+        assert_eq!(raws, [SYNCED, "[00:01.00]Cover"]);
+        assert!(found[0].confidence >= 0.8 && found[1].confidence < 0.8);
+
+        let job = LyricsJob::new(dir, Some(conf));
+        let best = job.lookup(&chain, &track).await.unwrap();
+        assert_eq!(best.as_deref(), Some(SYNCED));
+    }
+
+    #[tokio::test]
+    async fn matches_below_the_threshold_are_not_saved() {
+        let temp = temp_dir();
+        let dir = temp.path();
+        let track = track(dir);
+        let endpoint = stub(vec![record(1, "Someone Else", 260.0, "[00:01.00]Cover")]).await;
+        let conf = conf(dir, &endpoint, &["lrclib"]);
+        let chain = LyricsChain::from_config(dir, Some(conf.clone()));
+
+        assert_eq!(chain.search(&track).await.unwrap().len(), 1);
+        let job = LyricsJob::new(dir, Some(conf));
+        assert_eq!(job.lookup(&chain, &track).await, Ok(None));
+    }
+}
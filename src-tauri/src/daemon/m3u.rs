@@ -0,0 +1,158 @@
+use std::fmt::Write;
+
+use super::list::{ListEntry, PlaylistMetadata};
+
+/// Reads an extended or plain M3U playlist, `#EXTINF` lines describe the entry
+/// which follows them.
+pub fn parse(input: &str) -> (PlaylistMetadata, Vec<ListEntry>) {
+    let mut metadata = PlaylistMetadata::new();
+    let mut entries = vec![];
+    let mut info: Option<ListEntry> = None;
+
+    for line in input.trim_start_matches('\u{feff}').lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(rest) = line.strip_prefix("#EXTINF:") {
+            let album = info.take().and_then(|x| x.album);
+            info = Some(ListEntry {
+                album,
+                ..extinf(rest)
+            });
+        } else if let Some(album) = line.strip_prefix("#EXTALB:") {
+            info.get_or_insert_with(ListEntry::default).album = Some(album.trim().to_string());
+        } else if let Some(name) = line.strip_prefix("#PLAYLIST:") {
+            metadata.insert("Name".to_string(), name.trim().to_string());
+        } else if !line.starts_with('#') {
+            let mut entry = info.take().unwrap_or_default();
+            entry.location = line.to_string();
+            entries.push(entry);
+        }
+    }
+
+    (metadata, entries)
+}
+
+/// `#EXTINF:<seconds> [attributes],<artist> - <title>`, attributes like
+/// `tvg-id="..."` are skipped.
+fn extinf(rest: &str) -> ListEntry {
+    let (head, display) = split_display(rest);
+    let duration = head
+        .split_whitespace()
+        .next()
+        .and_then(|x| x.parse::<f64>().ok())
+        .filter(|x| *x >= 0.0)
+        .map(|x| x.round() as u64);
+
+    let mut entry = ListEntry {
+        duration,
+        ..Default::default()
+    };
+    entry.set_display(display);
+    entry
+}
+
+/// Splits on the first comma outside of a quoted attribute value.
+fn split_display(rest: &str) -> (&str, &str) {
+    let mut quoted = false;
+    for (i, c) in rest.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => return (&rest[..i], &rest[i + 1..]),
+            _ => {}
+        }
+    }
+
+    (rest, "")
+}
+
+/// Writes an extended M3U, UTF-8 so it is meant for `.m3u8` files.
+pub fn write(metadata: &PlaylistMetadata, entries: &[ListEntry]) -> String {
+    let mut output = String::from("#EXTM3U\n");
+    if let Some(name) = metadata.get("Name") {
+        let _ = writeln!(output, "#PLAYLIST:{}", one_line(name));
+    }
+
+    for entry in entries {
+        if entry.title.is_some() || entry.duration.is_some() {
+            let duration = entry.duration.map_or(-1, |x| x as i64);
+            let display = entry.display().unwrap_or_default();
+            let _ = writeln!(output, "#EXTINF:{duration},{}", one_line(&display));
+        }
+        if let Some(album) = &entry.album {
+            let _ = writeln!(output, "#EXTALB:{}", one_line(album));
+        }
+        let _ = writeln!(output, "{}", entry.location);
+    }
+
+    output
+}
+
+fn one_line(text: &str) -> String {
+    text.replace(['\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(
+        location: &str,
+        artist: Option<&str>,
+        title: Option<&str>,
+        duration: Option<u64>,
+    ) -> ListEntry {
+        ListEntry {
+            location: location.to_string(),
+            artist: artist.map(str::to_string),
+            title: title.map(str::to_string),
+            duration,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn reads_extended_and_plain_entries() {
+        let cases = [
+            ("song.mp3", entry("song.mp3", None, None, None)),
+            (
+                "#EXTINF:123,Artist - Title\nsong.mp3",
+                entry("song.mp3", Some("Artist"), Some("Title"), Some(123)),
+            ),
+            (
+                "#EXTINF:-1,Title\nhttp://radio/stream",
+                entry("http://radio/stream", None, Some("Title"), None),
+            ),
+            (
+                "#EXTINF:7.6 tvg-name=\"a, b\",Live - Now\nlive.ts",
+                entry("live.ts", Some("Live"), Some("Now"), Some(8)),
+            ),
+            (
+                "\u{feff}#EXTM3U\r\n\r\n# comment\r\n#EXTINF:1,A - B\r\n  spaced.flac  ",
+                entry("spaced.flac", Some("A"), Some("B"), Some(1)),
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse(input).1, [expected], "{input}");
+        }
+    }
+
+    #[test]
+    fn round_trips_names_and_albums() {
+        let input =
+            "#EXTM3U\n#PLAYLIST:Mix\n#EXTALB:Album\n#EXTINF:60,Artist - Title\na.flac\nb.flac\n";
+        let (metadata, entries) = parse(input);
+        assert_eq!(metadata.get("Name").map(String::as_str), Some("Mix"));
+        assert_eq!(entries[0].album.as_deref(), Some("Album"));
+        assert_eq!(entries[1], entry("b.flac", None, None, None));
+
+        let output = write(&metadata, &entries);
+        assert_eq!(
+            output,
+            "#EXTM3U\n#PLAYLIST:Mix\n#EXTINF:60,Artist - Title\n#EXTALB:Album\na.flac\nb.flac\n"
+        );
+        assert_eq!(parse(&output), (metadata, entries));
+    }
+}
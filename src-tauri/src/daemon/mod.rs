@@ -1,5 +1,29 @@
+pub mod analyzer;
 pub mod config;
+pub mod coverart;
 pub mod entry;
 pub mod global;
+pub mod index;
+pub mod infer;
+pub mod karaoke;
 pub mod list;
+pub mod lrc;
+pub mod lrclib;
+pub mod lyrics;
+pub mod lyrics_job;
+pub mod lyrics_provider;
+pub mod m3u;
+pub mod mojibake;
+pub mod palette;
+pub mod pls;
+pub mod query;
+pub mod search;
+pub mod smart;
+pub mod suggest;
+pub mod tags;
+#[cfg(test)]
+pub mod testing;
+pub mod thumbnail;
 pub mod utils;
+pub mod xml;
+pub mod xspf;
@@ -0,0 +1,254 @@
+use bitcode::{Decode, Encode};
+use encoding_rs::{Encoding, EUC_KR, GBK, SHIFT_JIS, UTF_8, WINDOWS_1251, WINDOWS_1252};
+
+use super::global::Track;
+
+// Under this score the original text is kept as is
+const MIN_CONFIDENCE: f32 = 0.6;
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Encode, Decode)]
+pub struct Mojibake {
+    pub field: String,
+    pub original: String,
+    pub suggestion: String,
+    pub encoding: String,
+    pub confidence: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Script {
+    Cyrillic,
+    Japanese,
+    Hangul,
+    Han,
+}
+
+fn script_of(c: char) -> Option<Script> {
+    match c as u32 {
+        // Only the common letters, rare ones show up a lot in wrongly decoded CJK text
+        0x0401 | 0x0410..=0x044F | 0x0451 => Some(Script::Cyrillic),
+        0x3040..=0x30FF | 0xFF66..=0xFF9F => Some(Script::Japanese),
+        0xAC00..=0xD7AF | 0x1100..=0x11FF | 0x3130..=0x318F => Some(Script::Hangul),
+        0x4E00..=0x9FFF | 0x3400..=0x4DBF => Some(Script::Han),
+        _ => None,
+    }
+}
+
+/// Gets back the bytes a legacy tag reader decoded as Latin-1 / Windows-1252.
+fn legacy_bytes(input: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(input.len());
+    for c in input.chars() {
+        if (c as u32) <= 0xFF {
+            bytes.push(c as u32 as u8);
+        } else {
+            let mut buf = [0; 4];
+            let (encoded, _, unmappable) = WINDOWS_1252.encode(c.encode_utf8(&mut buf));
+            if unmappable || encoded.len() != 1 {
+                return None;
+            }
+            bytes.push(encoded[0]);
+        }
+    }
+
+    Some(bytes)
+}
+
+fn is_legacy_high(c: char) -> bool {
+    let code = c as u32;
+    (0xA0..=0xFF).contains(&code) || (code > 0xFF && legacy_bytes(&c.to_string()).is_some())
+}
+
+/// Latin-1 strings with C1 controls or runs of accented letters and symbols
+/// are unlikely to be what the tagger meant.
+fn looks_garbled(input: &str) -> bool {
+    let mut run = 0;
+    for c in input.chars() {
+        if (0x80..=0x9F).contains(&(c as u32)) {
+            return true;
+        }
+        if is_legacy_high(c) {
+            run += 1;
+            if run >= 2 {
+                return true;
+            }
+        } else {
+            run = 0;
+        }
+    }
+
+    false
+}
+
+/// Scores how much a decoded string looks like real text written in `expected` scripts.
+fn plausibility(text: &str, expected: &[Script], single_byte: bool) -> f32 {
+    let mut total = 0;
+    let mut matching = 0;
+    for c in text.chars() {
+        let counted = if single_byte {
+            c.is_alphabetic()
+        } else {
+            !c.is_ascii()
+        };
+        if !counted {
+            continue;
+        }
+        total += 1;
+        if script_of(c).is_some_and(|x| expected.contains(&x)) {
+            matching += 1;
+        }
+    }
+
+    if total == 0 || matching == 0 {
+        return 0.0;
+    }
+
+    let ratio = matching as f32 / total as f32;
+    if single_byte {
+        ratio
+    } else {
+        // A couple of valid multibyte characters can happen by chance in accented latin text
+        ratio * (matching as f32 / 3.0).min(1.0)
+    }
+}
+
+pub fn detect(input: &str) -> Option<(String, &'static str, f32)> {
+    if input.is_ascii() || !looks_garbled(input) {
+        return None;
+    }
+
+    let bytes = legacy_bytes(input)?;
+
+    // UTF-8 read as Latin-1 is the most common case and strict UTF-8 decoding rarely succeeds by chance
+    if let Ok(text) = std::str::from_utf8(&bytes) {
+        if text != input {
+            return Some((text.to_string(), UTF_8.name(), 0.95));
+        }
+    }
+
+    let candidates: [(&'static Encoding, &[Script], bool); 4] = [
+        (SHIFT_JIS, &[Script::Japanese, Script::Han], false),
+        (WINDOWS_1251, &[Script::Cyrillic], true),
+        (EUC_KR, &[Script::Hangul], false),
+        (GBK, &[Script::Han], false),
+    ];
+
+    candidates
+        .iter()
+        .filter_map(|(encoding, scripts, single_byte)| {
+            let text = encoding.decode_without_bom_handling_and_without_replacement(&bytes)?;
+            let confidence = plausibility(&text, scripts, *single_byte);
+            Some((text.to_string(), encoding.name(), confidence))
+        })
+        .filter(|(_, _, confidence)| *confidence >= MIN_CONFIDENCE)
+        // On ties the first candidate wins, GBK accepts about any byte pair so it comes last
+        .reduce(|best, x| if x.2 > best.2 { x } else { best })
+}
+
+/// Checks the text tags of a freshly read track.
+pub fn scan(track: &Track) -> Vec<Mojibake> {
+    let mut fields = vec![
+        ("title", track.title.clone()),
+        ("album", track.album.clone()),
+        ("artists", track.artists.join(";")),
+        ("genres", track.genres.join(";")),
+    ];
+    if let Some(album_artist) = &track.album_artist {
+        fields.push(("album_artist", album_artist.clone()));
+    }
+
+    fields
+        .into_iter()
+        .filter_map(|(field, original)| {
+            let (suggestion, encoding, confidence) = detect(&original)?;
+            Some(Mojibake {
+                field: field.to_string(),
+                original,
+                suggestion,
+                encoding: encoding.to_string(),
+                confidence,
+            })
+        })
+        .collect()
+}
+
+/// Replaces the garbled fields of `track` by their suggested decoding.
+pub fn apply(track: &mut Track) -> Vec<String> {
+    let mut fields = vec![];
+    for fix in std::mem::take(&mut track.mojibake) {
+        match fix.field.as_str() {
+            "title" => track.title = fix.suggestion,
+            "album" => track.album = fix.suggestion,
+            "album_artist" => track.album_artist = Some(fix.suggestion),
+            "artists" => {
+                track.artists = fix
+                    .suggestion
+                    .split(';')
+                    .filter(|x| !x.is_empty())
+                    .map(|x| x.trim().to_string())
+                    .collect()
+            }
+            "genres" => {
+                track.genres = fix
+                    .suggestion
+                    .split(';')
+                    .map(|x| x.trim().to_string())
+                    .collect()
+            }
+            _ => continue,
+        }
+        fields.push(fix.field);
+    }
+
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `text` saved in `encoding` and read back as Windows-1252.
+    fn garble(text: &str, encoding: &'static Encoding) -> String {
+        let (bytes, _, _) = encoding.encode(text);
+        WINDOWS_1252
+            .decode_without_bom_handling(&bytes)
+            .0
+            .to_string()
+    }
+
+    #[test]
+    fn leaves_plausible_text_alone() {
+        for input in ["Radiohead", "Café", "Beyoncé", "Ñoño", "Sigur Rós", "Кино"] {
+            assert_eq!(detect(input), None, "{input}");
+        }
+    }
+
+    #[test]
+    fn recovers_legacy_encodings() {
+        let cases = [
+            ("Café del Mar", UTF_8, "UTF-8"),
+            ("Кино", WINDOWS_1251, "windows-1251"),
+            ("ドラゴンボール", SHIFT_JIS, "Shift_JIS"),
+            ("소녀시대", EUC_KR, "EUC-KR"),
+            ("王菲 - 红豆", GBK, "GBK"),
+        ];
+        for (text, encoding, name) in cases {
+            let input = garble(text, encoding);
+            let (suggestion, detected, _) = detect(&input).expect(text);
+            assert_eq!((suggestion.as_str(), detected), (text, name), "{input}");
+        }
+    }
+
+    #[test]
+    fn applies_the_suggestions() {
+        let mut track = Track {
+            artists: vec![garble("Кино", WINDOWS_1251)],
+            title: "Группа крови".to_string(),
+            ..Default::default()
+        };
+        track.mojibake = scan(&track);
+
+        assert_eq!(apply(&mut track), ["artists"]);
+        assert_eq!(track.artists, ["Кино"]);
+        assert!(track.mojibake.is_empty());
+    }
+}
@@ -0,0 +1,181 @@
+use bitcode::{Decode, Encode};
+use color_thief::ColorFormat;
+use std::path::Path;
+
+use super::global::{utils, Color};
+
+const WHITE: Color = Color {
+    r: 255,
+    g: 255,
+    b: 255,
+};
+
+const BLACK: Color = Color { r: 0, g: 0, b: 0 };
+
+// WCAG AA for normal text
+const MIN_CONTRAST: f64 = 4.5;
+
+#[derive(
+    serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Encode, Decode,
+)]
+pub struct Palette {
+    pub dominant: Color,
+    pub vibrant: Color,
+    pub muted: Color,
+    pub dark_vibrant: Color,
+    pub light_vibrant: Color,
+    pub dark_muted: Color,
+    pub light_muted: Color,
+    pub foreground: Color,
+    pub is_light: bool,
+}
+
+impl Palette {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let img = image::open(path).ok()?;
+        Self::from_image(img)
+    }
+
+    pub fn from_image(img: image::DynamicImage) -> Option<Self> {
+        // The palette does not need every pixel, a small copy is way faster to quantize
+        let img = img.thumbnail(256, 256);
+        let pixels = utils::get_image_buffer(img);
+        let colors: Vec<Color> = color_thief::get_palette(&pixels, ColorFormat::Rgb, 5, 8)
+            .ok()?
+            .into_iter()
+            .map(|c| Color {
+                r: c.r,
+                g: c.g,
+                b: c.b,
+            })
+            .collect();
+
+        let dominant = *colors.first()?;
+
+        let vibrant = colors
+            .iter()
+            .copied()
+            .max_by(|a, b| vibrancy(*a).total_cmp(&vibrancy(*b)))
+            .unwrap_or(dominant);
+        let muted = colors
+            .iter()
+            .copied()
+            .min_by(|a, b| vibrancy(*a).total_cmp(&vibrancy(*b)))
+            .unwrap_or(dominant);
+
+        let (vh, vs, _) = vibrant.to_hsl();
+        let (mh, ms, _) = muted.to_hsl();
+
+        let dark_vibrant = Color::from_hsl(vh, vs, 0.26);
+        let light_vibrant = Color::from_hsl(vh, vs, 0.74);
+        let dark_muted = Color::from_hsl(mh, ms.min(0.3), 0.26);
+        let light_muted = Color::from_hsl(mh, ms.min(0.3), 0.74);
+
+        let is_light = dominant.is_light_color();
+        let candidates = if is_light {
+            [dark_vibrant, dark_muted, BLACK]
+        } else {
+            [light_vibrant, light_muted, WHITE]
+        };
+
+        let foreground = candidates
+            .into_iter()
+            .find(|c| c.contrast_ratio(&dominant) >= MIN_CONTRAST)
+            .unwrap_or(
+                if WHITE.contrast_ratio(&dominant) >= BLACK.contrast_ratio(&dominant) {
+                    WHITE
+                } else {
+                    BLACK
+                },
+            );
+
+        Some(Self {
+            dominant,
+            vibrant,
+            muted,
+            dark_vibrant,
+            light_vibrant,
+            dark_muted,
+            light_muted,
+            foreground,
+            is_light,
+        })
+    }
+}
+
+/// Favours saturated colors that are neither too dark nor too bright.
+fn vibrancy(c: Color) -> f64 {
+    let (_, s, l) = c.to_hsl();
+    s * (1.0 - (l - 0.5).abs() * 2.0)
+}
+
+impl Color {
+    pub fn to_hsl(&self) -> (f64, f64, f64) {
+        let r = self.r as f64 / 255.0;
+        let g = self.g as f64 / 255.0;
+        let b = self.b as f64 / 255.0;
+
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let l = (max + min) / 2.0;
+        let d = max - min;
+
+        if d == 0.0 {
+            return (0.0, 0.0, l);
+        }
+
+        let s = d / (1.0 - (2.0 * l - 1.0).abs());
+        let h = if max == r {
+            ((g - b) / d).rem_euclid(6.0)
+        } else if max == g {
+            (b - r) / d + 2.0
+        } else {
+            (r - g) / d + 4.0
+        } * 60.0;
+
+        (h, s, l)
+    }
+
+    pub fn from_hsl(h: f64, s: f64, l: f64) -> Self {
+        let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+        let x = c * (1.0 - ((h / 60.0).rem_euclid(2.0) - 1.0).abs());
+        let m = l - c / 2.0;
+
+        let (r, g, b) = match (h.rem_euclid(360.0) / 60.0) as u32 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+
+        let to_u8 = |v: f64| ((v + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+
+        Self {
+            r: to_u8(r),
+            g: to_u8(g),
+            b: to_u8(b),
+        }
+    }
+
+    /// Relative luminance as defined by WCAG 2.x
+    pub fn relative_luminance(&self) -> f64 {
+        let channel = |v: u8| {
+            let v = v as f64 / 255.0;
+            if v <= 0.03928 {
+                v / 12.92
+            } else {
+                ((v + 0.055) / 1.055).powf(2.4)
+            }
+        };
+
+        0.2126 * channel(self.r) + 0.7152 * channel(self.g) + 0.0722 * channel(self.b)
+    }
+
+    pub fn contrast_ratio(&self, other: &Color) -> f64 {
+        let a = self.relative_luminance();
+        let b = other.relative_luminance();
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+}
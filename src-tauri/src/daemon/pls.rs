@@ -0,0 +1,122 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use super::list::{ListEntry, PlaylistMetadata};
+
+/// Reads the `FileN`, `TitleN` and `LengthN` keys of a `[playlist]` section,
+/// entries are ordered by their number.
+pub fn parse(input: &str) -> (PlaylistMetadata, Vec<ListEntry>) {
+    let mut entries: BTreeMap<u32, ListEntry> = BTreeMap::new();
+    for line in input.trim_start_matches('\u{feff}').lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_lowercase();
+        let value = value.trim();
+
+        let Some(split) = key.find(|c: char| c.is_ascii_digit()) else {
+            continue;
+        };
+        let Ok(number) = key[split..].parse::<u32>() else {
+            continue;
+        };
+        let entry = entries.entry(number).or_default();
+        match &key[..split] {
+            "file" => entry.location = value.to_string(),
+            "title" => entry.set_display(value),
+            "length" => {
+                entry.duration = value
+                    .parse::<i64>()
+                    .ok()
+                    .and_then(|x| u64::try_from(x).ok())
+            }
+            _ => {}
+        }
+    }
+
+    let entries = entries
+        .into_values()
+        .filter(|x| !x.location.is_empty())
+        .collect();
+    (PlaylistMetadata::new(), entries)
+}
+
+/// Version 2 PLS, it has no room for the playlist name or description.
+pub fn write(entries: &[ListEntry]) -> String {
+    let mut output = String::from("[playlist]\n");
+    for (i, entry) in entries.iter().enumerate() {
+        let number = i + 1;
+        let _ = writeln!(output, "File{number}={}", entry.location);
+        if let Some(display) = entry.display() {
+            let _ = writeln!(
+                output,
+                "Title{number}={}",
+                display.replace(['\r', '\n'], " ")
+            );
+        }
+        let duration = entry.duration.map_or(-1, |x| x as i64);
+        let _ = writeln!(output, "Length{number}={duration}");
+    }
+    let _ = writeln!(output, "NumberOfEntries={}", entries.len());
+    output.push_str("Version=2\n");
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(location: &str, title: Option<&str>, duration: Option<u64>) -> ListEntry {
+        ListEntry {
+            location: location.to_string(),
+            title: title.map(str::to_string),
+            duration,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn reads_numbered_keys() {
+        let cases = [
+            (
+                "[playlist]\nFile1=a.mp3\n",
+                vec![entry("a.mp3", None, None)],
+            ),
+            (
+                "[playlist]\nfile2=b.mp3\nTitle1=One\nFile1=a.mp3\nLength1=61\nLength2=-1\n",
+                vec![
+                    entry("a.mp3", Some("One"), Some(61)),
+                    entry("b.mp3", None, None),
+                ],
+            ),
+            ("[playlist]\nTitle1=Orphan\nNumberOfEntries=1\n", vec![]),
+            (
+                "\u{feff}[playlist]\r\nFile1 = http://radio/stream \r\n",
+                vec![entry("http://radio/stream", None, None)],
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse(input).1, expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn round_trips_entries() {
+        let entries = vec![
+            ListEntry {
+                location: "/music/a.flac".to_string(),
+                artist: Some("Artist".to_string()),
+                title: Some("Title".to_string()),
+                duration: Some(200),
+                ..Default::default()
+            },
+            ListEntry {
+                location: "b.flac".to_string(),
+                ..Default::default()
+            },
+        ];
+        let output = write(&entries);
+        assert!(output.ends_with("NumberOfEntries=2\nVersion=2\n"));
+        assert_eq!(parse(&output).1, entries);
+    }
+}
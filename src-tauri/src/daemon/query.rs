@@ -0,0 +1,816 @@
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+use tantivy::query::{AllQuery, BooleanQuery, Occur, Query};
+use tantivy::schema::Field;
+use tantivy::Index;
+
+use super::{
+    global::{Album, Facet, Track},
+    list::PlaylistData,
+    search::{self, Part},
+    utils::now,
+};
+
+/// Why a query could not be parsed, `position` is a byte offset in the query.
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct ParseError {
+    pub message: String,
+    pub position: usize,
+}
+
+impl ParseError {
+    fn new(position: usize, message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            position,
+        }
+    }
+}
+
+/// Fields looked up in the search index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextField {
+    Title,
+    Artist,
+    Album,
+    Lyrics,
+}
+
+/// Fields matched against the tracks in memory, as a case insensitive substring.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagField {
+    Genre,
+    Encoder,
+    Path,
+    /// File extension, `flac` or `mp3`
+    Format,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumberField {
+    Year,
+    Bitrate,
+    Duration,
+    Track,
+    Disc,
+    Added,
+    Plays,
+    /// Stars, from 0 to 5
+    Rating,
+}
+
+/// Words before a `:` which are read as a field, anything else is searched as is.
+const FIELDS: [&str; 16] = [
+    "title", "artist", "album", "lyrics", "genre", "encoder", "path", "format", "year", "bitrate",
+    "duration", "track", "disc", "added", "plays", "rating",
+];
+
+enum Filter {
+    Text(Option<TextField>, Part),
+    Tag(TagField, String),
+    Number(NumberField, (Bound<u64>, Bound<u64>)),
+}
+
+struct Clause {
+    negated: bool,
+    filter: Filter,
+}
+
+/// Clauses which all have to match.
+pub struct Group {
+    clauses: Vec<Clause>,
+}
+
+/// Groups joined by `OR`, which binds looser than the implicit `AND`.
+pub struct SearchQuery {
+    pub groups: Vec<Group>,
+}
+
+/// Something that can be filtered on the fields kept in memory.
+pub trait Attributes {
+    /// `None` when the field does not apply, like the bitrate of an album.
+    fn number(&self, field: NumberField) -> Option<Option<u64>>;
+    fn tags(&self, field: TagField) -> Option<Vec<&str>>;
+    /// The text behind an index field, only used when the index is unavailable.
+    fn text(&self, field: Option<TextField>) -> Option<Vec<&str>>;
+}
+
+impl Attributes for Track {
+    fn number(&self, field: NumberField) -> Option<Option<u64>> {
+        Some(match field {
+            NumberField::Year => self.album_year.map(u64::from),
+            NumberField::Bitrate => Some(self.bitrate.into()),
+            NumberField::Duration => Some(self.duration),
+            NumberField::Track => Some(self.track.into()),
+            NumberField::Disc => Some(self.disc.into()),
+            NumberField::Added => Some(self.created_at),
+            NumberField::Plays => Some(self.plays),
+            NumberField::Rating => self.rating.map(u64::from),
+        })
+    }
+
+    fn tags(&self, field: TagField) -> Option<Vec<&str>> {
+        Some(match field {
+            TagField::Genre => self.genres.iter().map(|x| x.as_str()).collect(),
+            TagField::Encoder => vec![self.encoder.as_str()],
+            TagField::Path => vec![self.file_path.as_str()],
+            TagField::Format => Path::new(&self.file_path)
+                .extension()
+                .and_then(|x| x.to_str())
+                .into_iter()
+                .collect(),
+        })
+    }
+
+    fn text(&self, field: Option<TextField>) -> Option<Vec<&str>> {
+        let mut found = vec![];
+        if matches!(field, None | Some(TextField::Title)) {
+            found.push(self.title.as_str());
+        }
+        if matches!(field, None | Some(TextField::Artist)) {
+            found.extend(self.artists.iter().map(|x| x.as_str()));
+        }
+        if matches!(field, None | Some(TextField::Album)) {
+            found.push(self.album.as_str());
+        }
+        if field == Some(TextField::Lyrics) {
+            found.extend(self.embeded_lyrics.as_deref());
+        }
+
+        Some(found)
+    }
+}
+
+impl Attributes for Album {
+    fn number(&self, field: NumberField) -> Option<Option<u64>> {
+        match field {
+            NumberField::Year => Some(self.year.map(u64::from)),
+            _ => None,
+        }
+    }
+
+    fn tags(&self, field: TagField) -> Option<Vec<&str>> {
+        match field {
+            TagField::Genre => Some(self.genres.iter().map(|x| x.as_str()).collect()),
+            TagField::Encoder => Some(vec![self.encoder.as_str()]),
+            TagField::Path | TagField::Format => None,
+        }
+    }
+
+    fn text(&self, field: Option<TextField>) -> Option<Vec<&str>> {
+        match field {
+            None => Some(vec![self.name.as_str(), self.artist.as_str()]),
+            Some(TextField::Album) => Some(vec![self.name.as_str()]),
+            Some(TextField::Artist) => Some(vec![self.artist.as_str()]),
+            _ => None,
+        }
+    }
+}
+
+/// Playlists, artists and genres are only searched by their text.
+impl Attributes for PlaylistData {
+    fn number(&self, _: NumberField) -> Option<Option<u64>> {
+        None
+    }
+
+    fn tags(&self, _: TagField) -> Option<Vec<&str>> {
+        None
+    }
+
+    fn text(&self, field: Option<TextField>) -> Option<Vec<&str>> {
+        field.is_none().then(|| {
+            ["Name", "Description"]
+                .iter()
+                .filter_map(|x| self.metadata.get(*x).map(|x| x.as_str()))
+                .collect()
+        })
+    }
+}
+
+impl Attributes for Facet {
+    fn number(&self, _: NumberField) -> Option<Option<u64>> {
+        None
+    }
+
+    fn tags(&self, _: TagField) -> Option<Vec<&str>> {
+        None
+    }
+
+    fn text(&self, field: Option<TextField>) -> Option<Vec<&str>> {
+        field.is_none().then(|| vec![self.name.as_str()])
+    }
+}
+
+impl Clause {
+    /// Whether the in memory part of the clause matches, text is left to the index.
+    fn check(&self, item: &impl Attributes) -> Option<bool> {
+        let found = match &self.filter {
+            Filter::Text(..) => return Some(true),
+            Filter::Tag(field, value) => {
+                let value = value.to_lowercase();
+                item.tags(*field)?
+                    .iter()
+                    .any(|x| x.to_lowercase().contains(&value))
+            }
+            Filter::Number(field, range) => match item.number(*field)? {
+                Some(x) => range.contains(&x),
+                None => false,
+            },
+        };
+
+        Some(found != self.negated)
+    }
+}
+
+impl Group {
+    /// The index query of the text clauses, `fields` maps them to the index fields
+    /// and returns nothing when a field does not exist there.
+    pub fn text_query(
+        &self,
+        index: &Index,
+        fields: impl Fn(Option<TextField>) -> Vec<Field>,
+    ) -> Option<Box<dyn Query>> {
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![];
+        for clause in &self.clauses {
+            let Filter::Text(field, part) = &clause.filter else {
+                continue;
+            };
+            let occur = if clause.negated {
+                Occur::MustNot
+            } else {
+                Occur::Must
+            };
+            if let Some(q) = search::part_query(index, &fields(*field), part) {
+                clauses.push((occur, q));
+            }
+        }
+
+        if clauses.is_empty() {
+            return None;
+        }
+        // Exclusions alone match nothing in tantivy
+        if clauses.iter().all(|(occur, _)| *occur == Occur::MustNot) {
+            clauses.push((Occur::Must, Box::new(AllQuery)));
+        }
+
+        Some(Box::new(BooleanQuery::new(clauses)))
+    }
+
+    pub fn has_text(&self) -> bool {
+        self.clauses
+            .iter()
+            .any(|x| matches!(x.filter, Filter::Text(..)))
+    }
+
+    /// Matches the text clauses as substrings once both sides went through
+    /// `normalize`, for when the index can't be searched.
+    pub fn contains(
+        &self,
+        item: &impl Attributes,
+        normalize: &mut impl FnMut(&str) -> String,
+    ) -> bool {
+        self.clauses.iter().all(|clause| {
+            let Filter::Text(field, part) = &clause.filter else {
+                return true;
+            };
+            let Some(values) = item.text(*field) else {
+                return false;
+            };
+            let needle = match part {
+                Part::Word(x) | Part::Phrase(x) => normalize(x),
+            };
+
+            values.iter().any(|x| normalize(x).contains(&needle)) != clause.negated
+        })
+    }
+
+    /// `false` as well when the group uses a field `item` does not have.
+    pub fn matches(&self, item: &impl Attributes) -> bool {
+        self.clauses.iter().all(|x| x.check(item).unwrap_or(false))
+    }
+
+    /// Playlists, artists and genres are found by free text only.
+    pub fn applies_to_collections(&self) -> bool {
+        self.clauses
+            .iter()
+            .all(|x| matches!(x.filter, Filter::Text(None, _)))
+    }
+
+    /// Albums only have a name, an artist, a year, genres and an encoder.
+    pub fn applies_to_albums(&self) -> bool {
+        self.clauses.iter().all(|x| match x.filter {
+            Filter::Text(field, _) => matches!(
+                field,
+                None | Some(TextField::Album) | Some(TextField::Artist)
+            ),
+            Filter::Tag(field, _) => !matches!(field, TagField::Path | TagField::Format),
+            Filter::Number(field, _) => field == NumberField::Year,
+        })
+    }
+}
+
+impl SearchQuery {
+    /// Matches every clause in memory, text ones as substrings after `normalize`.
+    /// A query without terms matches everything.
+    pub fn matches(
+        &self,
+        item: &impl Attributes,
+        normalize: &mut impl FnMut(&str) -> String,
+    ) -> bool {
+        self.groups.is_empty()
+            || self
+                .groups
+                .iter()
+                .any(|x| x.matches(item) && x.contains(item, normalize))
+    }
+
+    /// Plain words and phrases, searched like before field queries existed.
+    pub fn is_plain(&self) -> bool {
+        self.groups.len() == 1
+            && self.groups[0]
+                .clauses
+                .iter()
+                .all(|x| !x.negated && matches!(x.filter, Filter::Text(None, _)))
+    }
+}
+
+enum Token {
+    Or,
+    Term {
+        negated: bool,
+        field: Option<String>,
+        value: String,
+        quoted: bool,
+    },
+}
+
+/// Reads a quoted string starting after the opening quote at `start`.
+fn quoted(input: &str, start: usize) -> Result<(String, usize), ParseError> {
+    match input[start + 1..].find('"') {
+        Some(end) => Ok((
+            input[start + 1..start + 1 + end].to_string(),
+            start + end + 2,
+        )),
+        None => Err(ParseError::new(start, "Unclosed quote")),
+    }
+}
+
+fn lex(input: &str) -> Result<Vec<(usize, Token)>, ParseError> {
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < input.len() {
+        let rest = &input[i..];
+        let c = rest.chars().next().unwrap_or(' ');
+        if c.is_whitespace() {
+            i += c.len_utf8();
+            continue;
+        }
+        if c == '|' {
+            tokens.push((i, Token::Or));
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        // A `-` on its own is a plain word, like in `AC/DC - Back in Black`
+        let negated = c == '-' && rest[1..].starts_with(|x: char| !x.is_whitespace());
+        if negated {
+            i += 1;
+        }
+
+        if input[i..].starts_with('"') {
+            let (value, end) = quoted(input, i)?;
+            tokens.push((
+                start,
+                Token::Term {
+                    negated,
+                    field: None,
+                    value,
+                    quoted: true,
+                },
+            ));
+            i = end;
+            continue;
+        }
+
+        let len = input[i..]
+            .find(|x: char| x.is_whitespace() || x == '"')
+            .unwrap_or(input.len() - i);
+        let word = &input[i..i + len];
+        i += len;
+
+        if word == "OR" && !negated {
+            tokens.push((start, Token::Or));
+            continue;
+        }
+
+        // `Re:Zero` is a title, not a field
+        let field = word
+            .split_once(':')
+            .filter(|(name, _)| FIELDS.iter().any(|x| x.eq_ignore_ascii_case(name)));
+        let token = match field {
+            // `artist:"pink floyd"`
+            Some((name, "")) if input[i..].starts_with('"') => {
+                let (value, end) = quoted(input, i)?;
+                i = end;
+                Token::Term {
+                    negated,
+                    field: Some(name.to_lowercase()),
+                    value,
+                    quoted: true,
+                }
+            }
+            Some((name, value)) => Token::Term {
+                negated,
+                field: Some(name.to_lowercase()),
+                value: value.to_string(),
+                quoted: false,
+            },
+            None => Token::Term {
+                negated,
+                field: None,
+                value: word.to_string(),
+                quoted: false,
+            },
+        };
+        tokens.push((start, token));
+    }
+
+    Ok(tokens)
+}
+
+/// Days between 1970-01-01 and a date of the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let month = month as i64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146097 + doe - 719468
+}
+
+/// `2024`, `2024-05` or `2024-05-17` as the first and last second it covers.
+fn date(value: &str) -> Option<(u64, u64)> {
+    let parts: Vec<&str> = value.split('-').collect();
+    let year: i64 = parts[0].parse().ok()?;
+    let month = |x: &str| x.parse::<u32>().ok().filter(|x| (1..=12).contains(x));
+
+    let (first, next) = match parts[..] {
+        [_] => (days_from_civil(year, 1, 1), days_from_civil(year + 1, 1, 1)),
+        [_, m] => {
+            let m = month(m)?;
+            let next = if m == 12 {
+                days_from_civil(year + 1, 1, 1)
+            } else {
+                days_from_civil(year, m + 1, 1)
+            };
+            (days_from_civil(year, m, 1), next)
+        }
+        [_, m, d] => {
+            let d = d.parse::<u32>().ok().filter(|x| (1..=31).contains(x))?;
+            let first = days_from_civil(year, month(m)?, d);
+            (first, first + 1)
+        }
+        _ => return None,
+    };
+    if first < 0 {
+        return None;
+    }
+
+    Some((first as u64 * 86400, next as u64 * 86400 - 1))
+}
+
+/// `3:25` or `1:02:03`, plain numbers are seconds.
+fn duration(value: &str) -> Option<u64> {
+    value
+        .split(':')
+        .try_fold(0u64, |acc, x| Some(acc * 60 + x.parse::<u64>().ok()?))
+}
+
+/// `30d`, `2w`, `6m`, `1y` or `12h`.
+fn age(value: &str) -> Option<u64> {
+    let unit = match value.chars().last()? {
+        'h' => 3600,
+        'd' => 86400,
+        'w' => 7 * 86400,
+        'm' => 30 * 86400,
+        'y' => 365 * 86400,
+        _ => return None,
+    };
+    let count: u64 = value[..value.len() - 1].parse().ok()?;
+
+    Some(count * unit)
+}
+
+/// A single value as the interval it covers. Ages are counted back from now so
+/// they compare the other way round, `relative` tells the caller to flip them.
+fn interval(field: NumberField, name: &str, value: &str) -> Result<(u64, u64, bool), String> {
+    let found = match field {
+        NumberField::Added => match age(value) {
+            Some(x) => {
+                let at = now().saturating_sub(x);
+                return Ok((at, at, true));
+            }
+            None => date(value),
+        },
+        NumberField::Duration => duration(value).map(|x| (x, x)),
+        _ => value.parse().ok().map(|x| (x, x)),
+    };
+
+    found.map(|(a, b)| (a, b, false)).ok_or(match field {
+        NumberField::Added => {
+            format!("`{name}` expects a date like 2024-05-17 or an age like 30d, got `{value}`")
+        }
+        NumberField::Duration => {
+            format!("`{name}` expects seconds or minutes like 3:25, got `{value}`")
+        }
+        _ => format!("`{name}` expects a number, got `{value}`"),
+    })
+}
+
+fn range(field: NumberField, name: &str, value: &str) -> Result<(Bound<u64>, Bound<u64>), String> {
+    if let Some((from, to)) = value.split_once("..") {
+        if from.is_empty() && to.is_empty() {
+            return Err(format!("`{name}` range needs at least one end"));
+        }
+        let from = (!from.is_empty())
+            .then(|| interval(field, name, from))
+            .transpose()?;
+        let to = (!to.is_empty())
+            .then(|| interval(field, name, to))
+            .transpose()?;
+        let relative = from.or(to).is_some_and(|x| x.2);
+        let (from, to) = if relative { (to, from) } else { (from, to) };
+
+        return Ok(match (from, to) {
+            (Some(a), Some(b)) => (Bound::Included(a.0.min(b.0)), Bound::Included(a.1.max(b.1))),
+            (Some(a), None) => (Bound::Included(a.0), Bound::Unbounded),
+            (None, Some(b)) => (Bound::Unbounded, Bound::Included(b.1)),
+            (None, None) => (Bound::Unbounded, Bound::Unbounded),
+        });
+    }
+
+    let (op, rest) = [">=", "<=", ">", "<", "="]
+        .iter()
+        .find_map(|op| value.strip_prefix(op).map(|rest| (*op, rest)))
+        .unwrap_or(("", value));
+    let (lo, hi, relative) = interval(field, name, rest)?;
+    // `added:30d` reads as "within the last 30 days"
+    let op = match (op, relative) {
+        ("" | "=", true) => ">=",
+        (">", true) => "<",
+        (">=", true) => "<=",
+        ("<", true) => ">",
+        ("<=", true) => ">=",
+        (op, _) => op,
+    };
+
+    Ok(match op {
+        ">" => (Bound::Excluded(hi), Bound::Unbounded),
+        ">=" => (Bound::Included(lo), Bound::Unbounded),
+        "<" => (Bound::Unbounded, Bound::Excluded(lo)),
+        "<=" => (Bound::Unbounded, Bound::Included(hi)),
+        _ => (Bound::Included(lo), Bound::Included(hi)),
+    })
+}
+
+fn clause(
+    position: usize,
+    negated: bool,
+    field: Option<String>,
+    value: String,
+    quoted: bool,
+) -> Result<Clause, ParseError> {
+    let part = |value: String| {
+        if quoted {
+            Part::Phrase(value)
+        } else {
+            Part::Word(value)
+        }
+    };
+    let Some(name) = field else {
+        return Ok(Clause {
+            negated,
+            filter: Filter::Text(None, part(value)),
+        });
+    };
+    if value.trim().is_empty() {
+        return Err(ParseError::new(
+            position,
+            format!("`{name}:` needs a value"),
+        ));
+    }
+
+    let text = match name.as_str() {
+        "title" => Some(TextField::Title),
+        "artist" => Some(TextField::Artist),
+        "album" => Some(TextField::Album),
+        "lyrics" => Some(TextField::Lyrics),
+        _ => None,
+    };
+    let tag = match name.as_str() {
+        "genre" => Some(TagField::Genre),
+        "encoder" => Some(TagField::Encoder),
+        "path" => Some(TagField::Path),
+        "format" => Some(TagField::Format),
+        _ => None,
+    };
+    let number = match name.as_str() {
+        "year" => Some(NumberField::Year),
+        "bitrate" => Some(NumberField::Bitrate),
+        "duration" => Some(NumberField::Duration),
+        "track" => Some(NumberField::Track),
+        "disc" => Some(NumberField::Disc),
+        "added" => Some(NumberField::Added),
+        "plays" => Some(NumberField::Plays),
+        "rating" => Some(NumberField::Rating),
+        _ => None,
+    };
+
+    let filter = match (text, tag, number) {
+        (Some(field), ..) => Filter::Text(Some(field), part(value)),
+        (_, Some(field), _) => Filter::Tag(field, value),
+        (.., Some(_)) if quoted => {
+            return Err(ParseError::new(
+                position,
+                format!("`{name}` can not be a quoted phrase"),
+            ))
+        }
+        (.., Some(field)) => Filter::Number(
+            field,
+            range(field, &name, &value).map_err(|e| ParseError::new(position, e))?,
+        ),
+        _ => {
+            return Err(ParseError::new(
+                position,
+                format!(
+                    "Unknown field `{name}`, expected one of {}",
+                    FIELDS.join(", ")
+                ),
+            ))
+        }
+    };
+
+    Ok(Clause { negated, filter })
+}
+
+/// Parses queries like `artist:radiohead year:1995..2000 -live bitrate:>900 added:<30d`.
+///
+/// Words and `"quoted phrases"` without a field search titles, artists and albums,
+/// `-` excludes a term and `OR` (or `|`) separates alternatives.
+pub fn parse(input: &str) -> Result<SearchQuery, ParseError> {
+    let mut groups = vec![];
+    let mut clauses = vec![];
+    let mut last_or = None;
+    for (position, token) in lex(input)? {
+        match token {
+            Token::Or => {
+                if clauses.is_empty() {
+                    return Err(ParseError::new(position, "`OR` needs a term on both sides"));
+                }
+                groups.push(Group {
+                    clauses: std::mem::take(&mut clauses),
+                });
+                last_or = Some(position);
+            }
+            Token::Term {
+                negated,
+                field,
+                value,
+                quoted,
+            } => clauses.push(clause(position, negated, field, value, quoted)?),
+        }
+    }
+
+    if clauses.is_empty() {
+        if let Some(position) = last_or {
+            return Err(ParseError::new(position, "`OR` needs a term on both sides"));
+        }
+    } else {
+        groups.push(Group { clauses });
+    }
+
+    Ok(SearchQuery { groups })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tokens as `|` and `-field:value`, phrases in quotes.
+    fn tokens(input: &str) -> Vec<String> {
+        lex(input)
+            .unwrap()
+            .into_iter()
+            .map(|(_, token)| match token {
+                Token::Or => "|".to_string(),
+                Token::Term {
+                    negated,
+                    field,
+                    value,
+                    quoted,
+                } => format!(
+                    "{}{}{}",
+                    if negated { "-" } else { "" },
+                    field.map_or(String::new(), |x| format!("{x}:")),
+                    if quoted {
+                        format!("\"{value}\"")
+                    } else {
+                        value
+                    }
+                ),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lexes_terms_fields_and_alternatives() {
+        let cases: [(&str, &[&str]); 12] = [
+            ("creep", &["creep"]),
+            ("  two   words ", &["two", "words"]),
+            ("artist:radiohead", &["artist:radiohead"]),
+            ("Artist:Radiohead", &["artist:Radiohead"]),
+            ("artist:\"pink floyd\"", &["artist:\"pink floyd\""]),
+            ("-live \"ok computer\"", &["-live", "\"ok computer\""]),
+            ("a OR b | c", &["a", "|", "b", "|", "c"]),
+            ("-OR", &["-OR"]),
+            ("Re:Zero", &["Re:Zero"]),
+            ("Halo: Reach", &["Halo:", "Reach"]),
+            ("AC/DC - Back", &["AC/DC", "-", "Back"]),
+            (
+                "year:1990..1999 -genre:pop",
+                &["year:1990..1999", "-genre:pop"],
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(tokens(input), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn reports_where_a_query_is_wrong() {
+        let cases = [
+            ("\"open", 0),
+            ("a \"open", 2),
+            ("title:", 0),
+            ("OR a", 0),
+            ("a |", 2),
+            ("year:abc", 0),
+            ("x year:\"1990\"", 2),
+            ("added:..", 0),
+        ];
+        for (input, position) in cases {
+            let error = parse(input).err();
+            assert_eq!(error.map(|x| x.position), Some(position), "{input}");
+        }
+    }
+
+    #[test]
+    fn plain_text_that_looks_like_a_field_parses() {
+        for input in [
+            "Re:Zero",
+            "Halo: Reach",
+            "AC/DC - Back in Black",
+            "-",
+            "12:30",
+        ] {
+            let query = parse(input).unwrap();
+            assert!(query.is_plain(), "{input}");
+        }
+    }
+
+    #[test]
+    fn numbers_become_ranges() {
+        let cases = [
+            ("year:1995", (Bound::Included(1995), Bound::Included(1995))),
+            ("year:>1995", (Bound::Excluded(1995), Bound::Unbounded)),
+            ("year:<=1995", (Bound::Unbounded, Bound::Included(1995))),
+            (
+                "year:2000..1990",
+                (Bound::Included(1990), Bound::Included(2000)),
+            ),
+            ("duration:3:25..", (Bound::Included(205), Bound::Unbounded)),
+            ("plays:..10", (Bound::Unbounded, Bound::Included(10))),
+        ];
+        for (input, expected) in cases {
+            let query = parse(input).unwrap();
+            let Filter::Number(_, range) = &query.groups[0].clauses[0].filter else {
+                panic!("{input} is not a number filter");
+            };
+            assert_eq!(*range, expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn dates_cover_whole_days_months_and_years() {
+        assert_eq!(date("1970"), Some((0, 365 * 86400 - 1)));
+        assert_eq!(date("2024-02"), Some((1706745600, 1709251199)));
+        assert_eq!(date("2024-05-17"), Some((1715904000, 1715990399)));
+        assert_eq!(date("2024-13"), None);
+        assert_eq!(date("1969"), None);
+        assert_eq!(age("2w"), Some(14 * 86400));
+        assert_eq!(age("30"), None);
+    }
+}
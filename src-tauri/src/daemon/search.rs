@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use tantivy::query::{
+    BooleanQuery, BoostQuery, ConstScoreQuery, FuzzyTermQuery, Occur, PhraseQuery, Query, TermQuery,
+};
+use tantivy::schema::{Field, IndexRecordOption};
+use tantivy::{Index, Term};
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum HitKind {
+    Track,
+    Album,
+    Playlist,
+    Artist,
+    Genre,
+}
+
+impl HitKind {
+    pub const ALL: [HitKind; 5] = [
+        Self::Track,
+        Self::Album,
+        Self::Playlist,
+        Self::Artist,
+        Self::Genre,
+    ];
+
+    /// Kinds living in the collections index.
+    pub const COLLECTIONS: [HitKind; 3] = [Self::Playlist, Self::Artist, Self::Genre];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Track => "track",
+            Self::Album => "album",
+            Self::Playlist => "playlist",
+            Self::Artist => "artist",
+            Self::Genre => "genre",
+        }
+    }
+
+    /// Results of plain words shown at once, filters list everything.
+    fn page_size(&self) -> usize {
+        match self {
+            Self::Track => 20,
+            _ => 10,
+        }
+    }
+}
+
+/// Which results of each kind to return, `kind` keeps to one of them.
+#[derive(serde::Deserialize, Debug, Default, Clone, Copy)]
+pub struct Page {
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+    pub kind: Option<HitKind>,
+}
+
+impl Page {
+    pub fn wants(&self, kind: HitKind) -> bool {
+        self.kind.is_none_or(|x| x == kind)
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset.unwrap_or(0)
+    }
+
+    /// `None` for everything.
+    pub fn limit(&self, kind: HitKind, plain: bool) -> Option<usize> {
+        self.limit.or(plain.then(|| kind.page_size()))
+    }
+
+    /// How many of the best hits have to be looked up to fill the page.
+    pub fn fetch(&self, kind: HitKind, plain: bool) -> Option<usize> {
+        self.limit(kind, plain).map(|x| x + self.offset())
+    }
+}
+
+/// One entry of the ranked results, `id` is a track path, an album id, a playlist
+/// path or the lowercase name of an artist or a genre.
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct SearchHit {
+    pub kind: HitKind,
+    pub id: String,
+    pub score: f32,
+}
+
+/// Collects hits, keeping the best score of items matched more than once.
+#[derive(Default)]
+pub struct Hits {
+    hits: Vec<SearchHit>,
+    seen: HashMap<(HitKind, String), usize>,
+}
+
+impl Hits {
+    pub fn add(&mut self, kind: HitKind, id: &str, score: f32) {
+        match self.seen.get(&(kind, id.to_string())) {
+            Some(&i) => self.hits[i].score = self.hits[i].score.max(score),
+            None => {
+                self.seen.insert((kind, id.to_string()), self.hits.len());
+                self.hits.push(SearchHit {
+                    kind,
+                    id: id.to_string(),
+                    score,
+                });
+            }
+        }
+    }
+
+    /// Best first, items with the same score keep the order they were added in.
+    pub fn sorted(mut self) -> Vec<SearchHit> {
+        self.hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        self.hits
+    }
+}
+
+/// A bare word or a `"quoted phrase"` of a query.
+pub enum Part {
+    Word(String),
+    Phrase(String),
+}
+
+/// Runs `text` through the analyser of `field` so queries match what was indexed.
+pub fn tokens(index: &Index, field: Field, text: &str) -> Vec<String> {
+    let Ok(mut analyzer) = index.tokenizer_for_field(field) else {
+        return text.split_whitespace().map(|x| x.to_lowercase()).collect();
+    };
+
+    let mut tokens = vec![];
+    let mut stream = analyzer.token_stream(text);
+    while stream.advance() {
+        tokens.push(stream.token().text.clone());
+    }
+
+    tokens
+}
+
+fn distance(token: &str) -> u8 {
+    match token.chars().count() {
+        0..=2 => 0,
+        3..=5 => 1,
+        _ => 2,
+    }
+}
+
+/// Exact matches are scored with BM25 and boosted, typos and unfinished words still match.
+fn token_query(field: Field, token: &str) -> Box<dyn Query> {
+    let term = Term::from_field_text(field, token);
+    let exact = TermQuery::new(term.clone(), IndexRecordOption::WithFreqs);
+
+    Box::new(BooleanQuery::new(vec![
+        (
+            Occur::Should,
+            Box::new(BoostQuery::new(Box::new(exact), 2.0)),
+        ),
+        (
+            Occur::Should,
+            Box::new(FuzzyTermQuery::new_prefix(term, distance(token), true)),
+        ),
+    ]))
+}
+
+fn field_query(index: &Index, field: Field, part: &Part) -> Option<Box<dyn Query>> {
+    let (text, phrase) = match part {
+        Part::Word(x) => (x, false),
+        Part::Phrase(x) => (x, true),
+    };
+    let tokens = tokens(index, field, text);
+
+    match tokens.len() {
+        0 => None,
+        1 => Some(token_query(field, &tokens[0])),
+        _ if phrase => {
+            let terms = tokens
+                .iter()
+                .map(|x| Term::from_field_text(field, x))
+                .collect();
+            Some(Box::new(PhraseQuery::new(terms)))
+        }
+        // A word like `AC/DC` is split by the analyser, every piece has to match
+        _ => Some(Box::new(BooleanQuery::new(
+            tokens
+                .iter()
+                .map(|x| (Occur::Must, token_query(field, x)))
+                .collect(),
+        ))),
+    }
+}
+
+/// Restricts `q` to the documents of the collections index holding `kind`.
+pub fn of_kind(q: Box<dyn Query>, field: Field, kind: HitKind) -> Box<dyn Query> {
+    let kind = TermQuery::new(
+        Term::from_field_text(field, kind.name()),
+        IndexRecordOption::Basic,
+    );
+
+    // Scored zero so collections rank against tracks and albums on their text
+    Box::new(BooleanQuery::new(vec![
+        (Occur::Must, q),
+        (
+            Occur::Must,
+            Box::new(ConstScoreQuery::new(Box::new(kind), 0.0)),
+        ),
+    ]))
+}
+
+/// Matches `part` in at least one of `fields`.
+pub fn part_query(index: &Index, fields: &[Field], part: &Part) -> Option<Box<dyn Query>> {
+    let any_field: Vec<(Occur, Box<dyn Query>)> = fields
+        .iter()
+        .filter_map(|&field| field_query(index, field, part))
+        .map(|x| (Occur::Should, x))
+        .collect();
+
+    if any_field.is_empty() {
+        None
+    } else {
+        Some(Box::new(BooleanQuery::new(any_field)))
+    }
+}
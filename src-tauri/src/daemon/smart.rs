@@ -0,0 +1,178 @@
+use base64::{engine::general_purpose::URL_SAFE, Engine as _};
+use std::{cmp::Ordering, io, path::Path};
+
+use super::{
+    global::{Media, Track},
+    list::PlaylistMetadata,
+    query::{self, ParseError, SearchQuery},
+};
+
+pub const EXTENSION: &str = "smartlist";
+
+pub fn is_smart<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .is_some_and(|x| x.eq_ignore_ascii_case(EXTENSION))
+}
+
+/// Conditions nested in `all` and `any` groups, a condition is a search query
+/// like `genre:rock year:1990..1999 -live`.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Rule {
+    All { all: Vec<Rule> },
+    Any { any: Vec<Rule> },
+    Query(String),
+}
+
+enum Compiled {
+    All(Vec<Compiled>),
+    Any(Vec<Compiled>),
+    Query(SearchQuery),
+}
+
+impl Rule {
+    fn compile(&self) -> Result<Compiled, ParseError> {
+        Ok(match self {
+            Self::All { all } => {
+                Compiled::All(all.iter().map(Self::compile).collect::<Result<_, _>>()?)
+            }
+            Self::Any { any } => {
+                Compiled::Any(any.iter().map(Self::compile).collect::<Result<_, _>>()?)
+            }
+            Self::Query(q) => Compiled::Query(query::parse(q)?),
+        })
+    }
+}
+
+impl Compiled {
+    fn matches(&self, track: &Track, normalize: &mut impl FnMut(&str) -> String) -> bool {
+        match self {
+            Self::All(rules) => rules.iter().all(|x| x.matches(track, normalize)),
+            Self::Any(rules) => rules.iter().any(|x| x.matches(track, normalize)),
+            Self::Query(q) => q.matches(track, normalize),
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortField {
+    Title,
+    Artist,
+    Album,
+    Year,
+    Added,
+    Plays,
+    Rating,
+    Duration,
+    Format,
+    Track,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy)]
+pub struct Sort {
+    pub field: SortField,
+    #[serde(default)]
+    pub descending: bool,
+}
+
+impl Sort {
+    fn compare(&self, x: &Track, y: &Track) -> Ordering {
+        let text = |x: &str, y: &str| x.to_lowercase().cmp(&y.to_lowercase());
+        let format = |x: &Track| {
+            Path::new(&x.file_path)
+                .extension()
+                .map(|x| x.to_string_lossy().to_lowercase())
+        };
+        let order = match self.field {
+            SortField::Title => text(&x.title, &y.title),
+            SortField::Artist => text(&x.artists.join(", "), &y.artists.join(", ")),
+            SortField::Album => text(&x.album, &y.album),
+            SortField::Year => x.album_year.cmp(&y.album_year),
+            SortField::Added => x.created_at.cmp(&y.created_at),
+            SortField::Plays => x.plays.cmp(&y.plays),
+            SortField::Rating => x.rating.cmp(&y.rating),
+            SortField::Duration => x.duration.cmp(&y.duration),
+            SortField::Format => format(x).cmp(&format(y)),
+            SortField::Track => (x.disc, x.track).cmp(&(y.disc, y.track)),
+        };
+
+        if self.descending {
+            order.reverse()
+        } else {
+            order
+        }
+    }
+}
+
+/// A playlist whose tracks are the ones matching its rules, saved as JSON in a
+/// `.smartlist` file next to the `.playlist` ones. The rules are evaluated again
+/// each time the playlist is requested, relative dates stay current.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct SmartList {
+    #[serde(default)]
+    pub metadata: PlaylistMetadata,
+    pub rules: Rule,
+    /// Later keys break the ties of the earlier ones
+    #[serde(default)]
+    pub sort: Vec<Sort>,
+    pub limit: Option<usize>,
+}
+
+impl SmartList {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let input = std::fs::read_to_string(path)?;
+        serde_json::from_str(&input).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let output = serde_json::to_string_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        std::fs::write(path, output)
+    }
+
+    /// Saves it in the playlists directory, returns its `path_base64`.
+    pub fn create<P: AsRef<Path>>(&self, audio_dir: P) -> io::Result<String> {
+        let playlists_dir = audio_dir.as_ref().join("Playlists");
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .create(&playlists_dir)?;
+
+        let path = playlists_dir.join(format!("{}.{EXTENSION}", uuid::Uuid::new_v4()));
+        self.save(&path)?;
+
+        Ok(URL_SAFE.encode(format!("{}", path.display()).as_bytes()))
+    }
+
+    /// Errors when a condition isn't a valid search query.
+    pub fn validate(&self) -> Result<(), ParseError> {
+        self.rules.compile().map(|_| ())
+    }
+
+    /// Paths of the matching tracks of the library, sorted and limited.
+    pub fn evaluate(
+        &self,
+        media: &Media,
+        normalize: &mut impl FnMut(&str) -> String,
+    ) -> Result<Vec<String>, ParseError> {
+        let rules = self.rules.compile()?;
+        let mut tracks: Vec<&Track> = media
+            .tracks
+            .iter()
+            .map(|x| x.track())
+            .filter(|x| rules.matches(x, normalize))
+            .collect();
+
+        tracks.sort_by(|x, y| {
+            self.sort.iter().fold(Ordering::Equal, |order, sort| {
+                order.then_with(|| sort.compare(x, y))
+            })
+        });
+        if let Some(limit) = self.limit {
+            tracks.truncate(limit);
+        }
+
+        Ok(tracks.into_iter().map(|x| x.file_path.clone()).collect())
+    }
+}
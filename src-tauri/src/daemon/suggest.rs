@@ -0,0 +1,130 @@
+use std::collections::{HashMap, HashSet};
+use tantivy::tokenizer::TextAnalyzer;
+
+use super::{analyzer, global::Media};
+
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum SuggestionKind {
+    Title,
+    Artist,
+    Album,
+    Genre,
+    Playlist,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct Suggestion {
+    pub kind: SuggestionKind,
+    pub text: String,
+    /// Album id or playlist `path_base64`, to open it without searching
+    pub id: Option<String>,
+    /// Tracks carrying this text, used to rank
+    pub count: usize,
+}
+
+/// Every title, artist, album, genre and playlist name once per kind, looked up
+/// by the start of any of its words in a sorted list.
+#[derive(Debug, Default)]
+pub struct Suggestions {
+    /// Most used first, so an index is also a rank
+    items: Vec<Suggestion>,
+    /// Normalised text from each word on, whether it starts after the first word
+    /// and the item
+    keys: Vec<(String, bool, usize)>,
+}
+
+impl Suggestions {
+    pub fn new(media: &Media, analyzer: &mut TextAnalyzer) -> Self {
+        let mut found: HashMap<(SuggestionKind, String), Suggestion> = HashMap::new();
+        let mut add = |kind, text: &str, id: Option<&str>| {
+            let text = text.trim();
+            if text.is_empty() {
+                return;
+            }
+
+            // Albums and playlists with the same name are still different ones
+            let key = id.map_or_else(|| text.to_lowercase(), str::to_string);
+            found
+                .entry((kind, key))
+                .or_insert_with(|| Suggestion {
+                    kind,
+                    text: text.to_string(),
+                    id: id.map(str::to_string),
+                    count: 0,
+                })
+                .count += 1;
+        };
+
+        for track in media.tracks.iter().map(|x| x.track()) {
+            add(SuggestionKind::Title, &track.title, None);
+            for artist in &track.artists {
+                add(SuggestionKind::Artist, artist, None);
+            }
+            add(SuggestionKind::Album, &track.album, Some(&track.album_id));
+            for genre in &track.genres {
+                add(SuggestionKind::Genre, genre, None);
+            }
+        }
+        for list in &media.playlists {
+            if let Some(name) = list.metadata.get("Name") {
+                add(SuggestionKind::Playlist, name, Some(&list.path_base64));
+            }
+        }
+
+        let mut items: Vec<Suggestion> = found.into_values().collect();
+        items.sort_by(|x, y| {
+            y.count
+                .cmp(&x.count)
+                .then(x.kind.cmp(&y.kind))
+                .then(x.text.cmp(&y.text))
+        });
+
+        let mut keys = vec![];
+        for (i, item) in items.iter().enumerate() {
+            let normalized = analyzer::normalize(analyzer, &item.text);
+            let mut rest = normalized.as_str();
+            let mut later = false;
+            while !rest.is_empty() {
+                keys.push((rest.to_string(), later, i));
+                rest = rest.split_once(' ').map_or("", |x| x.1);
+                later = true;
+            }
+        }
+        keys.sort_unstable();
+
+        Self { items, keys }
+    }
+
+    /// Items with a word starting with `prefix`, those starting with it first,
+    /// then the most used.
+    pub fn lookup(
+        &self,
+        prefix: &str,
+        analyzer: &mut TextAnalyzer,
+        limit: usize,
+    ) -> Vec<Suggestion> {
+        let prefix = analyzer::normalize(analyzer, prefix);
+        if prefix.is_empty() {
+            return vec![];
+        }
+
+        let first = self
+            .keys
+            .partition_point(|x| x.0.as_str() < prefix.as_str());
+        let mut matches: Vec<(bool, usize)> = self.keys[first..]
+            .iter()
+            .take_while(|x| x.0.starts_with(&prefix))
+            .map(|x| (x.1, x.2))
+            .collect();
+        matches.sort_unstable();
+
+        let mut seen = HashSet::new();
+        matches
+            .into_iter()
+            .filter(|x| seen.insert(x.1))
+            .take(limit)
+            .map(|x| self.items[x.1].clone())
+            .collect()
+    }
+}
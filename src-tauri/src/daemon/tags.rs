@@ -0,0 +1,206 @@
+use lofty::config::{ParseOptions, WriteOptions};
+use lofty::file::FileType;
+use lofty::id3::v2::{BinaryFrame, Frame, FrameId, Id3v2Tag, UnsynchronizedTextFrame};
+use lofty::mpeg::MpegFile;
+use lofty::picture::{MimeType, Picture, PictureType};
+use lofty::prelude::*;
+use lofty::tag::Tag;
+use lofty::TextEncoding;
+use std::path::Path;
+
+use super::{global::Track, karaoke};
+
+/// Returns a copy of the primary tag of `path`. When the file has none, the
+/// fields of its other tag (like the ID3v1 tag of an old MP3) are copied into one.
+fn primary_tag(path: &Path) -> Result<Tag, String> {
+    let tagged_file = lofty::read_from_path(path).map_err(|e| format!("{e}"))?;
+    let tag_type = tagged_file.primary_tag_type();
+
+    Ok(match tagged_file.primary_tag() {
+        Some(tag) => tag.clone(),
+        None => {
+            let mut tag = tagged_file
+                .first_tag()
+                .cloned()
+                .unwrap_or_else(|| Tag::new(tag_type));
+            tag.re_tag(tag_type);
+            tag
+        }
+    })
+}
+
+/// Opens the primary tag of `path` (creating it when the file has none),
+/// lets `edit` modify it and writes it back.
+pub fn edit_primary_tag<P, F>(path: P, edit: F) -> Result<(), String>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&mut Tag),
+{
+    let path = path.as_ref();
+    let mut tag = primary_tag(path)?;
+
+    edit(&mut tag);
+
+    tag.save_to_path(path, WriteOptions::default())
+        .map_err(|e| format!("{e}"))
+}
+
+/// Stores `input` as the lyrics of the file (USLT for ID3, LYRICS for Vorbis, ©lyr for MP4).
+/// Synced lyrics in MP3 files also get a `SYLT` frame, with word timings when known.
+pub fn embed_lyrics<P>(path: P, input: &str) -> Result<(), String>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    if is_mpeg(path) {
+        return embed_id3_lyrics(path, input);
+    }
+
+    let mut tag = primary_tag(path)?;
+    tag.insert_text(ItemKey::Lyrics, input.to_string());
+    tag.save_to_path(path, WriteOptions::default())
+        .map_err(|e| format!("{e}"))
+}
+
+/// Replaces the main `USLT` frame and the `SYLT` frame of an MP3 file, lyrics in
+/// other languages and the frames a generic `Tag` can't hold are kept.
+fn embed_id3_lyrics(path: &Path, input: &str) -> Result<(), String> {
+    // Closed before the tag is written back
+    let existing = {
+        let mut file = std::fs::File::open(path).map_err(|e| format!("{e}"))?;
+        let mpeg =
+            MpegFile::read_from(&mut file, ParseOptions::new()).map_err(|e| format!("{e}"))?;
+        mpeg.id3v2().cloned()
+    };
+    let mut id3 = match existing {
+        Some(tag) => tag,
+        None => Id3v2Tag::from(primary_tag(path)?),
+    };
+
+    // The frame without a description is the one players show, its language is kept
+    let language = id3
+        .unsync_text()
+        .find(|x| x.description.is_empty())
+        .map_or(*b"XXX", |x| x.language);
+    id3.insert(Frame::UnsynchronizedText(UnsynchronizedTextFrame::new(
+        TextEncoding::UTF8,
+        language,
+        String::new(),
+        input.to_string(),
+    )));
+
+    let id = FrameId::new("SYLT").map_err(|e| format!("{e}"))?;
+    let lines = karaoke::parse(input);
+    if lines.is_empty() {
+        // The timings of the previous lyrics would no longer match
+        id3.remove(&id).for_each(drop);
+    } else {
+        id3.insert(Frame::Binary(BinaryFrame::new(
+            id,
+            karaoke::to_sylt(&lines),
+        )));
+    }
+
+    id3.save_to_path(path, WriteOptions::default())
+        .map_err(|e| format!("{e}"))
+}
+
+/// Whether `path` is an MP3 file, judged by its extension.
+fn is_mpeg(path: &Path) -> bool {
+    path.extension().and_then(FileType::from_ext) == Some(FileType::Mpeg)
+}
+
+/// The body of the `SYLT` frame of an MP3 file, other files are not opened.
+pub fn read_sylt<P>(path: P) -> Option<Vec<u8>>
+where
+    P: AsRef<Path>,
+{
+    if !is_mpeg(path.as_ref()) {
+        return None;
+    }
+    let mut file = std::fs::File::open(path).ok()?;
+    let mpeg = MpegFile::read_from(&mut file, ParseOptions::new()).ok()?;
+    let id = FrameId::new("SYLT").ok()?;
+
+    match mpeg.id3v2()?.get(&id)? {
+        Frame::Binary(frame) => Some(frame.data.to_vec()),
+        _ => None,
+    }
+}
+
+/// Every `USLT` frame of an MP3 file as `(language, description, lyrics)`, other files
+/// are not opened.
+pub fn read_uslt<P>(path: P) -> Vec<(String, String, String)>
+where
+    P: AsRef<Path>,
+{
+    if !is_mpeg(path.as_ref()) {
+        return vec![];
+    }
+    let Ok(mut file) = std::fs::File::open(path) else {
+        return vec![];
+    };
+    let Some(id3) = MpegFile::read_from(&mut file, ParseOptions::new())
+        .ok()
+        .and_then(|x| x.id3v2().cloned())
+    else {
+        return vec![];
+    };
+
+    id3.unsync_text()
+        .map(|frame| {
+            (
+                String::from_utf8_lossy(&frame.language).to_lowercase(),
+                frame.description.to_string(),
+                frame.content.to_string(),
+            )
+        })
+        .collect()
+}
+
+pub fn embed_cover<P>(path: P, data: Vec<u8>, mime: MimeType) -> Result<(), String>
+where
+    P: AsRef<Path>,
+{
+    edit_primary_tag(path, |tag| {
+        tag.remove_picture_type(PictureType::CoverFront);
+        tag.push_picture(Picture::new_unchecked(
+            PictureType::CoverFront,
+            Some(mime),
+            None,
+            data,
+        ));
+    })
+}
+
+/// Writes the given `Track` fields (named like the struct fields) into the file tags.
+pub fn write_fields<P>(path: P, track: &Track, fields: &[String]) -> Result<(), String>
+where
+    P: AsRef<Path>,
+{
+    edit_primary_tag(path, |tag| {
+        for field in fields {
+            match field.as_str() {
+                "title" => tag.set_title(track.title.clone()),
+                "album" => tag.set_album(track.album.clone()),
+                "artists" => {
+                    tag.insert_text(ItemKey::TrackArtist, track.artists.join(";"));
+                }
+                "album_artist" => {
+                    if let Some(album_artist) = &track.album_artist {
+                        tag.insert_text(ItemKey::AlbumArtist, album_artist.clone());
+                    }
+                }
+                "track" => tag.set_track(track.track),
+                "disc" => tag.set_disk(track.disc),
+                "album_year" => {
+                    if let Some(year) = track.album_year {
+                        tag.set_year(year);
+                    }
+                }
+                "genres" => tag.set_genre(track.genres.join(";")),
+                _ => {}
+            }
+        }
+    })
+}
@@ -0,0 +1,27 @@
+use axum::Router;
+use tempfile::TempDir;
+
+use super::global::Track;
+
+/// Serves `app` on a free local port and returns its base url.
+pub async fn serve(app: Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    format!("http://{addr}")
+}
+
+/// A directory removed once dropped.
+pub fn temp_dir() -> TempDir {
+    tempfile::tempdir().unwrap()
+}
+
+pub fn track(title: &str, artist: &str, album: &str) -> Track {
+    Track {
+        title: title.to_string(),
+        artists: vec![artist.to_string()],
+        album: album.to_string(),
+        ..Default::default()
+    }
+}
@@ -0,0 +1,238 @@
+use image::{
+    codecs::{jpeg::JpegEncoder, webp::WebPEncoder},
+    imageops::FilterType,
+    ImageReader,
+};
+use std::{
+    collections::HashSet,
+    fs,
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+use tracing::{error, info, warn};
+
+use super::global::{check_dir, Media};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThumbnailFormat {
+    Webp,
+    Jpeg,
+}
+
+impl ThumbnailFormat {
+    pub fn from_name(name: &str) -> Self {
+        match name.to_lowercase().as_str() {
+            "jpg" | "jpeg" => Self::Jpeg,
+            _ => Self::Webp,
+        }
+    }
+
+    pub fn ext(&self) -> &'static str {
+        match self {
+            Self::Webp => "webp",
+            Self::Jpeg => "jpeg",
+        }
+    }
+
+    pub fn mime(&self) -> &'static str {
+        match self {
+            Self::Webp => "image/webp",
+            Self::Jpeg => "image/jpeg",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Thumbnail {
+    pub data: Vec<u8>,
+    pub etag: String,
+    pub mime: &'static str,
+}
+
+#[derive(Debug, Clone)]
+pub struct ThumbnailCache {
+    covers_dir: PathBuf,
+    thumbs_dir: PathBuf,
+    format: ThumbnailFormat,
+    /// JPEG quality, WebP thumbnails are always lossless
+    quality: u8,
+    sizes: Vec<u32>,
+    pregenerate: bool,
+}
+
+impl ThumbnailCache {
+    pub fn new(cache_dir: &Path, conf: Option<lorconf::Covers>) -> Self {
+        let default = lorconf::Covers::default();
+        let conf = conf.unwrap_or(default.clone());
+
+        let format = conf
+            .thumbnail_format
+            .or(default.thumbnail_format)
+            .unwrap_or_default();
+        let quality = conf
+            .thumbnail_quality
+            .or(default.thumbnail_quality)
+            .unwrap_or(85)
+            .clamp(1, 100);
+        let mut sizes = conf
+            .thumbnail_sizes
+            .or(default.thumbnail_sizes)
+            .unwrap_or_default();
+        sizes.retain(|s| *s > 0);
+        sizes.sort_unstable();
+        sizes.dedup();
+        let pregenerate = conf.pregenerate.unwrap_or(false);
+
+        let covers_dir = cache_dir.join("covers");
+        Self {
+            thumbs_dir: covers_dir.join("thumbs"),
+            covers_dir,
+            format: ThumbnailFormat::from_name(&format),
+            quality,
+            sizes,
+            pregenerate,
+        }
+    }
+
+    /// Snaps a requested box to the smallest standard size that covers it.
+    pub fn snap(&self, w: u32, h: u32) -> u32 {
+        let wanted = w.max(h);
+        for size in &self.sizes {
+            if *size >= wanted {
+                return *size;
+            }
+        }
+
+        self.sizes.last().copied().unwrap_or(wanted)
+    }
+
+    fn thumb_path(&self, handle: &str, size: u32) -> PathBuf {
+        let stem = Path::new(handle)
+            .file_stem()
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or(handle.to_string());
+
+        self.thumbs_dir.join(size.to_string()).join(format!(
+            "{stem}-q{}.{}",
+            self.quality,
+            self.format.ext()
+        ))
+    }
+
+    fn generate(&self, source: &Path, dest: &Path, size: u32) -> Option<Vec<u8>> {
+        let image = ImageReader::open(source).ok()?.decode().ok()?;
+        let image = image.resize(size, size, FilterType::Lanczos3);
+
+        let mut buf = Cursor::new(Vec::new());
+        let res = match self.format {
+            ThumbnailFormat::Webp => image
+                .to_rgba8()
+                .write_with_encoder(WebPEncoder::new_lossless(&mut buf)),
+            ThumbnailFormat::Jpeg => image
+                .to_rgb8()
+                .write_with_encoder(JpegEncoder::new_with_quality(&mut buf, self.quality)),
+        };
+
+        if let Err(e) = res {
+            error!("Unable to encode thumbnail for `{}`: {e}", source.display());
+            return None;
+        }
+
+        let data = buf.into_inner();
+        if let Some(parent) = dest.parent() {
+            check_dir(&parent.to_path_buf());
+        }
+        if let Err(e) = fs::write(dest, &data) {
+            warn!("Unable to write thumbnail `{}`: {e}", dest.display());
+        }
+
+        Some(data)
+    }
+
+    pub fn get(&self, handle: &str, size: u32) -> Option<Thumbnail> {
+        let source = self.covers_dir.join(handle);
+        if !source.is_file() {
+            return None;
+        }
+
+        let dest = self.thumb_path(handle, size);
+        let data = match fs::read(&dest) {
+            Ok(data) => data,
+            Err(_) => self.generate(&source, &dest, size)?,
+        };
+
+        Some(Thumbnail {
+            etag: etag(&data),
+            data,
+            mime: self.format.mime(),
+        })
+    }
+
+    /// Drops thumbnails of albums that are no longer in the library and,
+    /// when `pregenerate` is set, fills the cache for every remaining cover.
+    pub fn sync(&self, media: &Media) {
+        let ids: HashSet<&str> = media.albums.iter().map(|x| x.id.as_str()).collect();
+
+        if let Ok(size_dirs) = fs::read_dir(&self.thumbs_dir) {
+            for size_dir in size_dirs.flatten() {
+                let Ok(thumbs) = fs::read_dir(size_dir.path()) else {
+                    continue;
+                };
+                for thumb in thumbs.flatten() {
+                    let name = thumb.file_name().to_string_lossy().to_string();
+                    let id = name.split_once('-').map(|(id, _)| id).unwrap_or(&name);
+                    if !ids.contains(id) {
+                        info!("- thumbnail {name}");
+                        let _ = fs::remove_file(thumb.path());
+                    }
+                }
+            }
+        }
+
+        if !self.pregenerate {
+            return;
+        }
+
+        if let Ok(covers) = fs::read_dir(&self.covers_dir) {
+            for cover in covers.flatten() {
+                let path = cover.path();
+                if !path.is_file() {
+                    continue;
+                }
+                let handle = cover.file_name().to_string_lossy().to_string();
+                let id = handle.split_once('.').map(|(id, _)| id).unwrap_or(&handle);
+                if !ids.contains(id) {
+                    continue;
+                }
+                for size in &self.sizes {
+                    let dest = self.thumb_path(&handle, *size);
+                    if !dest.exists() {
+                        self.generate(&path, &dest, *size);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Drops every size of an album's thumbnails, e.g. once its cover changed.
+pub fn remove_thumbnails(covers_dir: &Path, album_id: &str) {
+    let prefix = format!("{album_id}-q");
+    let Ok(size_dirs) = fs::read_dir(covers_dir.join("thumbs")) else {
+        return;
+    };
+    for size_dir in size_dirs.flatten() {
+        let Ok(thumbs) = fs::read_dir(size_dir.path()) else {
+            continue;
+        };
+        for thumb in thumbs.flatten() {
+            if thumb.file_name().to_string_lossy().starts_with(&prefix) {
+                let _ = fs::remove_file(thumb.path());
+            }
+        }
+    }
+}
+
+pub fn etag(data: &[u8]) -> String {
+    format!("\"{:x}\"", md5::compute(data))
+}
@@ -2,12 +2,14 @@ use std::{
     fs,
     io::{Read, Write},
     path::{Path, PathBuf},
+    time::SystemTime,
 };
 
 use crate::daemon::global::utils::cache_audio_files;
 use crate::daemon::global::utils::get_audio_files;
 use crate::daemon::global::utils::read_cache_audio_files;
 use crate::daemon::global::Media;
+use crate::daemon::infer::PathTemplate;
 use tauri::Emitter;
 use tracing::{info, warn};
 
@@ -17,7 +19,19 @@ pub enum CacheCompareDiff {
     NoDiff,
 }
 
-pub async fn cache_resolve(cache_dir: &Path, win: Option<tauri::Window>) -> Media {
+/// Seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or(0)
+}
+
+pub async fn cache_resolve(
+    cache_dir: &Path,
+    templates: &[PathTemplate],
+    win: Option<tauri::Window>,
+) -> Media {
     info!("Starting cache process...");
     let p_string = cache_dir.join(".cache");
     let covers_dir = cache_dir.join("covers");
@@ -48,7 +62,7 @@ pub async fn cache_resolve(cache_dir: &Path, win: Option<tauri::Window>) -> Medi
                             if let Some(win) = win.clone() {
                                 let _ = win.emit("sync", msg);
                             }
-                            cache_data.add_media(file, &covers_dir);
+                            cache_data.add_media(file, &covers_dir, templates);
                         }
                     }
                     CacheCompareDiff::ToRemove { files } => {
@@ -79,7 +93,7 @@ pub async fn cache_resolve(cache_dir: &Path, win: Option<tauri::Window>) -> Medi
                     let _ = win.emit("sync", msg);
                 }
 
-                cache.add_media(file, &covers_dir);
+                cache.add_media(file, &covers_dir, templates);
             }
             needs_update = true;
         }
@@ -92,7 +106,7 @@ pub async fn cache_resolve(cache_dir: &Path, win: Option<tauri::Window>) -> Medi
             }
 
             info!("+ {}", file.display());
-            cache.add_media(file, &covers_dir);
+            cache.add_media(file, &covers_dir, templates);
         }
         needs_update = true;
     }
@@ -106,8 +120,6 @@ pub async fn cache_resolve(cache_dir: &Path, win: Option<tauri::Window>) -> Medi
 
     info!("cache process ended");
 
-    cache.create_search_index(cache_dir.to_path_buf());
-
     cache
 }
 
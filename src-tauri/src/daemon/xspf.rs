@@ -0,0 +1,197 @@
+use std::fmt::Write;
+
+use super::{
+    list::{self, ListEntry, PlaylistMetadata},
+    xml::{escape, unescape},
+};
+
+/// Playlist elements and the metadata keys they are kept under.
+const METADATA: [(&str, &str); 4] = [
+    ("title", "Name"),
+    ("creator", "Creator"),
+    ("annotation", "Description"),
+    ("image", "Image"),
+];
+
+/// Text of the first `<name>` element of `body`.
+fn element(body: &str, name: &str) -> Option<String> {
+    let open = format!("<{name}>");
+    let close = format!("</{name}>");
+    let start = body.find(&open)? + open.len();
+    let end = body[start..].find(&close)? + start;
+    let text = body[start..end].trim();
+    let text = text
+        .strip_prefix("<![CDATA[")
+        .and_then(|x| x.strip_suffix("]]>"))
+        .map_or_else(|| unescape(text), str::to_string);
+
+    (!text.is_empty()).then_some(text)
+}
+
+/// Reads the playlist metadata and the `<track>`s of its `<trackList>`.
+pub fn parse(input: &str) -> (PlaylistMetadata, Vec<ListEntry>) {
+    let (head, tracks) = input.split_once("<trackList").unwrap_or((input, ""));
+
+    let mut metadata = PlaylistMetadata::new();
+    for (name, key) in METADATA {
+        if let Some(value) = element(head, name) {
+            metadata.insert(key.to_string(), value);
+        }
+    }
+
+    let mut entries = vec![];
+    let mut rest = tracks;
+    while let Some(start) = rest.find("<track>") {
+        let after = &rest[start + "<track>".len()..];
+        let end = after.find("</track>").unwrap_or(after.len());
+        let track = &after[..end];
+        rest = &after[end..];
+
+        let Some(location) = element(track, "location") else {
+            continue;
+        };
+        // Paths are URIs, only file ones are decoded when resolved
+        let location = if location.starts_with("file://") || list::is_url(&location) {
+            location
+        } else {
+            list::percent_decode(&location)
+        };
+        entries.push(ListEntry {
+            location,
+            title: element(track, "title"),
+            artist: element(track, "creator"),
+            album: element(track, "album"),
+            duration: element(track, "duration")
+                .and_then(|x| x.parse::<u64>().ok())
+                .map(|x| (x + 500) / 1000),
+        });
+    }
+
+    (metadata, entries)
+}
+
+/// Location of an entry as a URI, absolute paths become `file://` ones.
+fn uri(location: &str) -> String {
+    if list::is_url(location) {
+        return location.to_string();
+    }
+
+    let path = location.replace('\\', "/");
+    match path.chars().nth(1) {
+        _ if path.starts_with('/') => format!("file://{}", list::percent_encode(&path)),
+        // `C:/Music`, the drive colon stays as is
+        Some(':') if path.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+            format!("file:///{}{}", &path[..2], list::percent_encode(&path[2..]))
+        }
+        _ => list::percent_encode(&path),
+    }
+}
+
+pub fn write(metadata: &PlaylistMetadata, entries: &[ListEntry]) -> String {
+    let mut output = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n",
+    );
+    for (name, key) in METADATA {
+        if let Some(value) = metadata.get(key) {
+            let _ = writeln!(output, "  <{name}>{}</{name}>", escape(value));
+        }
+    }
+
+    output.push_str("  <trackList>\n");
+    for entry in entries {
+        output.push_str("    <track>\n");
+        let _ = writeln!(
+            output,
+            "      <location>{}</location>",
+            escape(&uri(&entry.location))
+        );
+        let fields = [
+            ("title", &entry.title),
+            ("creator", &entry.artist),
+            ("album", &entry.album),
+        ];
+        for (name, value) in fields {
+            if let Some(value) = value {
+                let _ = writeln!(output, "      <{name}>{}</{name}>", escape(value));
+            }
+        }
+        if let Some(duration) = entry.duration {
+            let _ = writeln!(output, "      <duration>{}</duration>", duration * 1000);
+        }
+        output.push_str("    </track>\n");
+    }
+    output.push_str("  </trackList>\n</playlist>\n");
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_metadata_and_tracks() {
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?>
+<playlist version="1" xmlns="http://xspf.org/ns/0/">
+  <title>Rock &amp; Roll</title>
+  <annotation><![CDATA[<b>Loud</b>]]></annotation>
+  <trackList>
+    <track><location>file:///music/a%20b.flac</location><title>Title</title><creator>Artist</creator><duration>1499</duration></track>
+    <track><title>No location</title></track>
+    <track><location>relative%20dir/c.ogg</location></track>
+    <track><location>http://radio/stream?a=1&amp;b=2</location></track>
+  </trackList>
+</playlist>"#;
+
+        let (metadata, entries) = parse(input);
+        assert_eq!(
+            metadata.get("Name").map(String::as_str),
+            Some("Rock & Roll")
+        );
+        assert_eq!(
+            metadata.get("Description").map(String::as_str),
+            Some("<b>Loud</b>")
+        );
+
+        let locations: Vec<&str> = entries.iter().map(|x| x.location.as_str()).collect();
+        assert_eq!(
+            locations,
+            [
+                "file:///music/a%20b.flac",
+                "relative dir/c.ogg",
+                "http://radio/stream?a=1&b=2"
+            ]
+        );
+        assert_eq!(entries[0].title.as_deref(), Some("Title"));
+        assert_eq!(entries[0].artist.as_deref(), Some("Artist"));
+        assert_eq!(entries[0].duration, Some(1));
+    }
+
+    #[test]
+    fn writes_locations_as_uris() {
+        let cases = [
+            ("/music/a b.flac", "file:///music/a%20b.flac"),
+            ("C:\\Music\\a.flac", "file:///C:/Music/a.flac"),
+            ("dir/a b.flac", "dir/a%20b.flac"),
+            ("http://radio/stream", "http://radio/stream"),
+        ];
+        for (location, expected) in cases {
+            assert_eq!(uri(location), expected, "{location}");
+        }
+    }
+
+    #[test]
+    fn round_trips_entries() {
+        let metadata = PlaylistMetadata::from([("Name".to_string(), "A < B".to_string())]);
+        let entries = vec![ListEntry {
+            location: "dir/a & b.flac".to_string(),
+            title: Some("Title".to_string()),
+            artist: Some("Artist".to_string()),
+            album: Some("Album".to_string()),
+            duration: Some(200),
+        }];
+
+        let output = write(&metadata, &entries);
+        assert_eq!(parse(&output), (metadata, entries));
+    }
+}
@@ -2,13 +2,13 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 use base64::{engine::general_purpose::URL_SAFE, Engine as _};
-use daemon::{config::Dir, global::Media};
+use daemon::{config::Dir, global::Media, lrc::Lint};
 use lorconf::Config;
 use tauri_plugin_decorum::WebviewWindowExt;
 use tracing::warn;
 mod daemon;
 use crate::daemon::entry::start;
-use std::{env::consts::OS, fs::File, io::Write};
+use std::env::consts::OS;
 use tauri::{Emitter, Manager};
 use tauri_plugin_window_state::{AppHandleExt, StateFlags, WindowExt};
 
@@ -105,11 +105,54 @@ async fn sync_music(app: tauri::AppHandle, window: tauri::Window) {
     let _ = window.emit("endsync", "");
 }
 
+#[derive(serde::Serialize)]
+struct SaveLyrics {
+    lyrics: String,
+    mode: Option<String>,
+    format: Option<String>,
+}
+
 #[tauri::command]
-fn save_lyrics(input: String, path: String) {
-    let lrc_path = std::path::PathBuf::from(path).with_extension("lrc");
-    let mut lrc_file = File::create(lrc_path).unwrap();
-    let _ = lrc_file.write_all(input.as_bytes());
+async fn save_lyrics(
+    app: tauri::AppHandle,
+    input: String,
+    path: String,
+    mode: Option<String>,
+    format: Option<String>,
+) -> Result<Vec<Lint>, String> {
+    let conf_path = app.path().app_config_dir().unwrap().join("config.toml");
+    let endpoint = format!(
+        "http://{}/lyrics?path={}",
+        daemon_(conf_path),
+        URL_SAFE.encode(path.as_bytes())
+    );
+    let client = reqwest::Client::new();
+    let response = client
+        .put(endpoint)
+        .json(&SaveLyrics {
+            lyrics: input,
+            mode,
+            format,
+        })
+        .send()
+        .await
+        .map_err(|e| format!("{e}"))?;
+
+    if response.status().is_success() {
+        response.json().await.map_err(|e| format!("{e}"))
+    } else {
+        Err(response.text().await.unwrap_or_default())
+    }
+}
+
+#[tauri::command]
+fn set_lyrics_mode(app: tauri::AppHandle, mode: String) -> Config {
+    let path = app.path().app_config_dir().unwrap().join("config.toml");
+    let mut config = Config::get(&path);
+    lorconf::update_conf!(config, lyrics, save_mode, Some(mode));
+    Config::dump(&path, config.clone());
+
+    config
 }
 
 #[tauri::command]
@@ -263,6 +306,7 @@ async fn start_app(args: Vec<String>) -> Result<(), Box<dyn std::error::Error>>
             set_blur,
             set_host,
             set_port,
+            set_lyrics_mode,
             config,
             default_config,
             daemon_endpoint,
@@ -1,31 +1,64 @@
-import type { SearchResults } from './type';
+import type { SearchError, SearchResults, Suggestion, SuggestResponse } from './type';
 import { getContext, setContext } from 'svelte';
 import type Ws from './utils/websocket';
 
+function emptyResults(): SearchResults {
+	return {
+		albums: [],
+		tracks: [],
+		playlists: [],
+		artists: [],
+		genres: [],
+		lyrics: [],
+		hits: [],
+		counts: {}
+	};
+}
+
 export default class SearchSupervisor {
 	query: string = $state('');
 	socket: Ws | null = $state(null);
-	results: SearchResults = $state({
-		albums: [],
-		tracks: []
-	});
-	local_results: SearchResults = $state({
-		albums: [],
-		tracks: []
-	});
+	results: SearchResults = $state(emptyResults());
+	local_results: SearchResults = $state(emptyResults());
+	error: SearchError | null = $state(null);
+	local_error: SearchError | null = $state(null);
+	suggestions: Suggestion[] = $state([]);
 	initialized = $state(false);
 
 	isEmpty() {
-		return this.results.albums.length === 0 && this.results.tracks.length === 0;
+		return (
+			this.results.albums.length === 0 &&
+			this.results.tracks.length === 0 &&
+			this.results.playlists.length === 0 &&
+			this.results.artists.length === 0 &&
+			this.results.genres.length === 0
+		);
 	}
 
 	init(socket: Ws) {
 		socket.on('searchresponse', (res: SearchResults) => {
 			this.results = res;
+			this.error = null;
+		});
+
+		socket.on('searcherror', (err: SearchError) => {
+			this.error = err;
 		});
 
 		socket.on('localsr', (res: SearchResults) => {
 			this.local_results = res;
+			this.local_error = null;
+		});
+
+		socket.on('localsrerror', (err: SearchError) => {
+			this.local_error = err;
+		});
+
+		socket.on('suggestions', (res: SuggestResponse) => {
+			// Answers to earlier keystrokes can arrive late
+			if (res.prefix === this.query) {
+				this.suggestions = res.suggestions;
+			}
 		});
 
 		this.socket = socket;
@@ -34,10 +67,21 @@ export default class SearchSupervisor {
 
 	search() {
 		if (this.socket) {
+			this.suggestions = [];
 			if (this.query.length > 0) {
 				this.socket.emit('search', this.query);
 			} else {
-				this.results = { albums: [], tracks: [] };
+				this.results = emptyResults();
+			}
+		}
+	}
+
+	suggest() {
+		if (this.socket) {
+			if (this.query.trim().length > 0) {
+				this.socket.emit('suggest', this.query);
+			} else {
+				this.suggestions = [];
 			}
 		}
 	}
@@ -47,7 +91,7 @@ export default class SearchSupervisor {
 			if (term.length > 0) {
 				this.socket.emit('localsearch', term);
 			} else {
-				this.local_results = { albums: [], tracks: [] };
+				this.local_results = emptyResults();
 			}
 		}
 	}
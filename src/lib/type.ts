@@ -65,6 +65,18 @@ export type Color = {
 	b: u8;
 };
 
+export type Palette = {
+	dominant: Color;
+	vibrant: Color;
+	muted: Color;
+	dark_vibrant: Color;
+	light_vibrant: Color;
+	dark_muted: Color;
+	light_muted: Color;
+	foreground: Color;
+	is_light: boolean;
+};
+
 export type Cover = {
 	data: number[];
 	mime: string;
@@ -83,6 +95,7 @@ export type Album = {
 	encoder: string;
 	year?: u32;
 	id: string;
+	palette?: Option<Palette>;
 };
 
 export type SystemTime = number;
@@ -108,7 +121,21 @@ export type Track = {
 	disc: number;
 	encoder: string;
 	embeded_lyrics: Option<string>;
+	musicbrainz_release_id?: Option<string>;
+	inferred?: string[];
+	mojibake?: Mojibake[];
 	genres: string[];
+	plays?: u64;
+	/** Stars from 0 to 5 */
+	rating?: Option<u8>;
+};
+
+export type Mojibake = {
+	field: string;
+	original: string;
+	suggestion: string;
+	encoding: string;
+	confidence: number;
 };
 
 export type QueueTrack = Track & {
@@ -122,15 +149,103 @@ export type Playlist = {
 	path_base64: string;
 };
 
+/** A search query, or groups of rules which all or any have to match */
+export type SmartRule = string | { all: SmartRule[] } | { any: SmartRule[] };
+
+export type SmartSortField =
+	| 'title'
+	| 'artist'
+	| 'album'
+	| 'year'
+	| 'added'
+	| 'plays'
+	| 'rating'
+	| 'duration'
+	| 'format'
+	| 'track';
+
+/** Rules of a playlist stored in a `.smartlist` file, its tracks come from `/playlist/{path}` */
+export type SmartList = {
+	metadata: Record<string, string>;
+	rules: SmartRule;
+	sort: { field: SmartSortField; descending?: boolean }[];
+	limit: Option<usize>;
+};
+
 export type Media = {
 	tracks: Array<[string, Track]>;
 	albums: Album[];
 	playlists: Playlist[];
 };
 
+export type LyricsMatch = {
+	path: string;
+	/** HTML, matching words are wrapped in `<b>` */
+	snippet: string;
+	time: Option<u64>;
+	line: Option<string>;
+};
+
+/** A query that could not be parsed, `position` is a byte offset in it */
+export type SearchError = {
+	message: string;
+	position: number;
+};
+
+export type SearchHitKind = 'track' | 'album' | 'playlist' | 'artist' | 'genre';
+
+export type SearchHit = {
+	kind: SearchHitKind;
+	/** Track path, album id, playlist path or lowercase artist or genre name */
+	id: string;
+	score: number;
+};
+
+/** An artist or a genre, `albums` holds album ids */
+export type Facet = {
+	name: string;
+	tracks: number;
+	albums: string[];
+};
+
 export type SearchResults = {
 	albums: Array<Album>;
 	tracks: Array<Track>;
+	playlists: Array<Playlist>;
+	artists: Array<Facet>;
+	genres: Array<Facet>;
+	lyrics: Array<LyricsMatch>;
+	hits: Array<SearchHit>;
+	/** Total matches of each kind, the lists above only hold one page */
+	counts: Partial<Record<SearchHitKind, number>>;
+};
+
+export type SuggestionKind = 'title' | 'artist' | 'album' | 'genre' | 'playlist';
+
+export type Suggestion = {
+	kind: SuggestionKind;
+	text: string;
+	/** Album id or playlist `path_base64` */
+	id: Option<string>;
+	count: number;
+};
+
+export type SuggestResponse = {
+	prefix: string;
+	suggestions: Suggestion[];
+};
+
+export type SearchIndexStatus = 'ready' | 'building' | 'missing' | 'corrupt' | 'stale';
+
+export type SearchIndexHealth = {
+	status: SearchIndexStatus;
+	error: Option<string>;
+	version: number;
+	/** Analyser settings the index was built with */
+	analysis: string;
+	songs: number;
+	albums: number;
+	collections: number;
 };
 
 export type Line = {
@@ -200,9 +315,45 @@ export type Global = {
 	theme?: Theme;
 };
 
+export type Covers = {
+	thumbnail_format?: 'webp' | 'jpeg';
+	thumbnail_quality?: u8;
+	thumbnail_sizes?: u32[];
+	pregenerate?: boolean;
+};
+
+export type LyricsSaveMode = 'sidecar' | 'embedded' | 'both';
+
+export type LyricsSource = {
+	name: string;
+	endpoint: string;
+};
+
+export type LyricsConfig = {
+	save_mode?: LyricsSaveMode;
+	lrclib_endpoint?: string;
+	retry_after?: number;
+	providers?: string[];
+	local_dir?: string;
+	fetch_interval?: number;
+	fetch_retries?: number;
+	min_confidence?: number;
+	sources?: LyricsSource[];
+};
+
+export type SearchConfig = {
+	fold_accents?: boolean;
+	stemming?: boolean;
+	cjk_ngram?: number;
+	normalize_kana?: boolean;
+};
+
 export type Config = {
 	global?: Global;
 	network?: Network;
+	covers?: Covers;
+	lyrics?: LyricsConfig;
+	search?: SearchConfig;
 };
 
 type DeepRequired<T> = {
@@ -232,9 +383,63 @@ export enum QueueMode {
 	RepeatAll = 'repeat-all'
 }
 
+export type LyricsKind = 'synced' | 'plain' | 'instrumental';
+
+export type KaraokeWord = {
+	start: u64;
+	end: u64;
+	text: string;
+};
+
+export type KaraokeLine = {
+	start: u64;
+	end: u64;
+	text: string;
+	words: KaraokeWord[];
+};
+
+export type LyricsFormat = 'lrc' | 'elrc' | 'ttml';
+
+export type TrackLyrics = {
+	kind: Option<LyricsKind>;
+	lyrics: LyricLine[];
+	plain: Option<string>;
+	karaoke: KaraokeLine[];
+	variants: LyricsVariant[];
+};
+
+export type LyricsVariant = {
+	lang: string;
+	source: 'sidecar' | 'embedded';
+	lines: Option<string>[];
+};
+
 export type Lrc = {
+	kind: Option<LyricsKind>;
 	parsed: LyricLine[];
 	raw: string;
+	provider: string;
+	confidence: number;
+};
+
+export type LrcLintKind = 'unsorted' | 'overlap' | 'empty' | 'invalid';
+
+export type LrcLint = {
+	line: usize;
+	kind: LrcLintKind;
+	message: string;
+};
+
+export type LyricsJobProgress = {
+	total: number;
+	done: number;
+	saved: number;
+	failed: number;
+	/** Tracks that already had lyrics */
+	skipped: number;
+	paused: boolean;
+	running: boolean;
+	current: Option<string>;
 };
 
 export type LyricsResponse = {